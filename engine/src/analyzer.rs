@@ -0,0 +1,175 @@
+use std::borrow::Cow;
+
+use rust_stemmers::Algorithm;
+use unicode_segmentation::UnicodeSegmentation;
+
+// splits text into words, each with its byte offset into the text
+pub trait Tokenizer: Send + Sync {
+    fn words<'a>(&self, text: &'a str) -> Vec<(usize, &'a str)>;
+}
+
+pub trait Normalizer: Send + Sync {
+    fn normalize<'a>(&self, word: &'a str) -> Cow<'a, str>;
+}
+
+pub trait Stemmer: Send + Sync {
+    fn stem<'a>(&self, word: &'a str) -> Cow<'a, str>;
+}
+
+// turns text into words and words into the keys stored in the term map.
+// documents and queries must go through the same analyzer, or their terms won't line up.
+pub trait Analyzer: Send + Sync {
+    fn words<'a>(&self, text: &'a str) -> Vec<(usize, &'a str)>;
+
+    // the surface form of a word, used for exact matching
+    fn normalize<'a>(&self, word: &'a str) -> Cow<'a, str>;
+
+    // the stemmed form of a word
+    fn term<'a>(&self, word: &'a str) -> Cow<'a, str>;
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "persistence", derive(serde::Serialize, serde::Deserialize))]
+pub struct TextAnalyzer<T, N, S> {
+    pub tokenizer: T,
+    pub normalizer: N,
+    pub stemmer: S,
+}
+
+pub type DefaultAnalyzer = TextAnalyzer<UnicodeWords, Lowercase, Snowball>;
+
+impl DefaultAnalyzer {
+    pub fn for_language(language: Option<Algorithm>) -> DefaultAnalyzer {
+        TextAnalyzer::new(UnicodeWords, Lowercase, Snowball(language))
+    }
+}
+
+impl<T, N, S> TextAnalyzer<T, N, S> {
+    pub fn new(tokenizer: T, normalizer: N, stemmer: S) -> TextAnalyzer<T, N, S> {
+        TextAnalyzer {
+            tokenizer,
+            normalizer,
+            stemmer,
+        }
+    }
+}
+
+impl<T: Tokenizer, N: Normalizer, S: Stemmer> Analyzer for TextAnalyzer<T, N, S> {
+    #[inline(always)]
+    fn words<'a>(&self, text: &'a str) -> Vec<(usize, &'a str)> {
+        self.tokenizer.words(text)
+    }
+
+    #[inline(always)]
+    fn normalize<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        self.normalizer.normalize(word)
+    }
+
+    fn term<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        match self.normalizer.normalize(word) {
+            Cow::Borrowed(word) => self.stemmer.stem(word),
+            Cow::Owned(word) => Cow::Owned(self.stemmer.stem(&word).into_owned()),
+        }
+    }
+}
+
+/* tokenizers */
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "persistence", derive(serde::Serialize, serde::Deserialize))]
+pub struct UnicodeWords;
+
+impl Tokenizer for UnicodeWords {
+    fn words<'a>(&self, text: &'a str) -> Vec<(usize, &'a str)> {
+        text.unicode_word_indices().collect()
+    }
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "persistence", derive(serde::Serialize, serde::Deserialize))]
+pub struct Whitespace;
+
+impl Tokenizer for Whitespace {
+    fn words<'a>(&self, text: &'a str) -> Vec<(usize, &'a str)> {
+        text.split_whitespace()
+            .map(|word| (word.as_ptr() as usize - text.as_ptr() as usize, word))
+            .collect()
+    }
+}
+
+/* normalizers */
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "persistence", derive(serde::Serialize, serde::Deserialize))]
+pub struct Lowercase;
+
+impl Normalizer for Lowercase {
+    fn normalize<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        if word.chars().all(|c| c.is_lowercase() || !c.is_alphabetic()) {
+            Cow::Borrowed(word)
+        } else {
+            Cow::Owned(word.to_lowercase())
+        }
+    }
+}
+
+impl Normalizer for () {
+    #[inline(always)]
+    fn normalize<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        Cow::Borrowed(word)
+    }
+}
+
+// runs both normalizers, left to right
+impl<A: Normalizer, B: Normalizer> Normalizer for (A, B) {
+    fn normalize<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        match self.0.normalize(word) {
+            Cow::Borrowed(word) => self.1.normalize(word),
+            Cow::Owned(word) => Cow::Owned(self.1.normalize(&word).into_owned()),
+        }
+    }
+}
+
+/* stemmers */
+
+// snowball stemming in the given language, or no stemming at all if it's None
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "persistence", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snowball(pub Option<Algorithm>);
+
+impl Snowball {
+    pub fn new(language: Algorithm) -> Snowball {
+        Snowball(Some(language))
+    }
+
+    pub fn disabled() -> Snowball {
+        Snowball(None)
+    }
+
+    pub fn language(&self) -> Option<Algorithm> {
+        self.0
+    }
+}
+
+impl Default for Snowball {
+    fn default() -> Self {
+        Snowball(Some(Algorithm::English))
+    }
+}
+
+impl Stemmer for Snowball {
+    #[inline(always)]
+    fn stem<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        match self.0 {
+            Some(language) => rust_stemmers::Stemmer::create(language).stem(word),
+            None => Cow::Borrowed(word),
+        }
+    }
+}
+
+impl Stemmer for () {
+    #[inline(always)]
+    fn stem<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        Cow::Borrowed(word)
+    }
+}
@@ -1,14 +1,20 @@
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, HashSet},
     fs::{File, OpenOptions},
     io,
     path::Path,
 };
 
-use storage::{MultiMap, RkyvMap, SerializableToFile, SimpleStorage};
+use rust_stemmers::Algorithm;
+use storage::{CompressedMap, MultiMap, RkyvMap, SerializableToFile, SimpleStorage};
 
 use crate::{
+    analyzer::{Analyzer, DefaultAnalyzer},
+    completion::CompletionBuilder,
+    postings::PostingIndex,
+    ranking::CorpusStats,
     searcher::SearchEngine,
+    segment::Segmentation,
     sentence::{Sentence, SentenceId},
     term_map::TermMap,
     Database, DocumentMetadata, SentenceMetadata,
@@ -23,7 +29,7 @@ fn open_mapfile(path: impl AsRef<Path>) -> io::Result<File> {
 }
 
 #[derive(Default)]
-pub struct DatabaseBuilder<D, DM, SM>
+pub struct DatabaseBuilder<D, DM, SM, A = DefaultAnalyzer>
 where
     D: rkyv::Archive + SerializableToFile,
     DM: DocumentMetadata,
@@ -34,7 +40,29 @@ where
     doc_metadata: BTreeMap<u32, DM>,
     doc_storage: HashMap<u32, D>,
     make_sentence_metadata: Option<Box<dyn Fn(&str) -> SM>>,
-    term_map: TermMap,
+    segmentation: Segmentation,
+    // exact terms that are kept in sentences, but get no posting list
+    stopwords: HashSet<u32>,
+    // the distinct exact terms seen for each stemmed term
+    stem_forms: HashMap<u32, Vec<u32>>,
+    // store posting lists as delta encoded blocks instead of plain arrays
+    compress_postings: bool,
+    // give exact terms their stem's postings instead of their own
+    stemmed_only: bool,
+    stats: CorpusStats,
+    term_map: TermMap<A>,
+}
+
+impl<D, DM, SM> DatabaseBuilder<D, DM, SM, DefaultAnalyzer>
+where
+    D: rkyv::Archive + SerializableToFile,
+    DM: DocumentMetadata,
+    SM: SentenceMetadata,
+{
+    // stem in the given language, or don't stem at all if it's None
+    pub fn for_language(language: Option<Algorithm>) -> DatabaseBuilder<D, DM, SM> {
+        DatabaseBuilder::with_analyzer(DefaultAnalyzer::for_language(language))
+    }
 }
 
 #[derive(Debug, Default)]
@@ -45,24 +73,83 @@ pub struct DocumentData<'a, D: rkyv::Archive + SerializableToFile, DM: DocumentM
     pub data: D,
 }
 
-impl<D, DM, SM> DatabaseBuilder<D, DM, SM>
+impl<D, DM, SM, A> DatabaseBuilder<D, DM, SM, A>
 where
     D: rkyv::Archive + SerializableToFile,
     DM: DocumentMetadata,
     SM: SentenceMetadata,
+    A: Analyzer,
 {
+    pub fn with_analyzer(analyzer: A) -> DatabaseBuilder<D, DM, SM, A> {
+        DatabaseBuilder {
+            sentence_map: HashMap::new(),
+            term_to_sentence: HashMap::new(),
+            doc_metadata: BTreeMap::new(),
+            doc_storage: HashMap::new(),
+            make_sentence_metadata: None,
+            segmentation: Segmentation::default(),
+            stopwords: HashSet::new(),
+            stem_forms: HashMap::new(),
+            compress_postings: false,
+            stemmed_only: false,
+            stats: CorpusStats::default(),
+            term_map: TermMap::new(analyzer),
+        }
+    }
+
+    pub fn analyzer(&self) -> &A {
+        &self.term_map.analyzer
+    }
+
     pub fn set_sentence_metadata_creator(&mut self, f: impl Fn(&str) -> SM + 'static) {
         self.make_sentence_metadata = Some(Box::new(f));
     }
 
+    pub fn set_segmentation(&mut self, segmentation: Segmentation) {
+        self.segmentation = segmentation;
+    }
+
+    // stopwords are still recorded in each sentence's terms, so phrases containing them can be
+    // matched exactly, but they aren't indexed. they go by their exact form, so other words with
+    // the same stem are still found, and apply to every document no matter when they're set.
+    pub fn set_stopwords<'a>(&mut self, words: impl IntoIterator<Item = &'a str>) {
+        for word in words {
+            self.stopwords.insert(self.term_map.intern_exact(word));
+        }
+    }
+
+    // compressed posting lists take a fraction of the space, at the cost of decoding them when
+    // queried. intersections skip over blocks that can't contain a match without decoding them
+    pub fn set_compressed_postings(&mut self, compressed: bool) {
+        self.compress_postings = compressed;
+    }
+
+    // exact terms get posting lists of their own wherever they differ from their stem's, so
+    // exact queries (=word) can be told apart from stemmed ones. without them, the index is smaller
+    // and exact queries match like stemmed ones
+    pub fn set_exact_postings(&mut self, indexed: bool) {
+        self.stemmed_only = !indexed;
+    }
+
     pub fn add_document(&mut self, doc: DocumentData<D, DM>) {
-        let sentences = self.term_map.tokenize_all(doc.text, |v| {
-            if let Some(make_metadata) = self.make_sentence_metadata.as_ref() {
-                make_metadata(v)
-            } else {
-                SM::default()
-            }
-        });
+        let sentences = self
+            .term_map
+            .tokenize_segments(doc.text, &self.segmentation, |v| {
+                if let Some(make_metadata) = self.make_sentence_metadata.as_ref() {
+                    make_metadata(v)
+                } else {
+                    SM::default()
+                }
+            });
+
+        let doc_len: usize = sentences.iter().map(|s| s.tokens.len()).sum();
+        if self.stats.doc_lengths.len() <= doc.id as usize {
+            self.stats.doc_lengths.resize(doc.id as usize + 1, 0);
+        }
+        self.stats.doc_lengths[doc.id as usize] = doc_len as u32;
+        self.stats.documents += 1;
+        self.stats.sentences += sentences.len() as u64;
+        self.stats.tokens += doc_len as u64;
 
         self.sentence_map.reserve(sentences.len());
         self.term_to_sentence.reserve(sentences.len() * 16);
@@ -71,7 +158,14 @@ where
             let id = SentenceId::new(doc.id, sentence_idx as u32);
             assert!(bytemuck::cast::<SentenceId, u64>(id) != 0);
 
-            for term in &sentence.terms {
+            for (term, exact_term) in sentence.terms.iter().zip(&sentence.exact_terms) {
+                let forms = self.stem_forms.entry(*term).or_default();
+                if !forms.contains(exact_term) {
+                    forms.push(*exact_term);
+                }
+            }
+
+            for term in sentence.terms.iter().chain(&sentence.exact_terms) {
                 let entry = self.term_to_sentence.entry(*term).or_insert_with(Vec::new);
 
                 entry.push(SentenceId {
@@ -95,7 +189,48 @@ where
     //     self.build_in_tempdir(TempDir::new_in(dir)?)
     // }
 
-    pub fn build_in(mut self, dir: impl AsRef<Path>) -> io::Result<Database<D, DM, SM>> {
+    pub fn build_in(mut self, dir: impl AsRef<Path>) -> io::Result<Database<D, DM, SM, A>> {
+        // a stem keeps its postings as long as one of its forms isn't a stopword
+        for stopword in &self.stopwords {
+            self.term_to_sentence.remove(stopword);
+        }
+        for (stem, forms) in &self.stem_forms {
+            if forms.iter().all(|form| self.stopwords.contains(form)) {
+                self.term_to_sentence.remove(stem);
+            }
+        }
+
+        let mut completions = CompletionBuilder::default();
+        for sentence in self.sentence_map.values() {
+            completions.add_sentence(sentence, &self.stopwords);
+        }
+
+        // an exact term that's the only form of its stem has the same postings as the stem, so it
+        // takes over the stem's id rather than storing them twice
+        let aliases: HashMap<u32, u32> = self
+            .stem_forms
+            .iter()
+            .filter(|(_, forms)| self.stemmed_only || forms.len() == 1)
+            .flat_map(|(stem, forms)| forms.iter().map(|form| (*form, *stem)))
+            .collect();
+
+        if !aliases.is_empty() {
+            for exact in aliases.keys() {
+                self.term_to_sentence.remove(exact);
+            }
+
+            for id in self.term_map.exact.values_mut() {
+                *id = aliases.get(id).copied().unwrap_or(*id);
+            }
+
+            for sentence in self.sentence_map.values_mut() {
+                for term in &mut sentence.exact_terms {
+                    *term = aliases.get(term).copied().unwrap_or(*term);
+                }
+                sentence.index_terms();
+            }
+        }
+
         for (_, val) in self.term_to_sentence.iter_mut() {
             val.sort();
             val.dedup();
@@ -103,10 +238,15 @@ where
 
         let dir = dir.as_ref();
 
-        let sentence_index: MultiMap<u32, SentenceId> = MultiMap::multi_from_map(
-            self.term_to_sentence,
-            open_mapfile(dir.join("sentences.index.joie"))?,
-        )?;
+        let index_file = open_mapfile(dir.join("sentences.index.joie"))?;
+        let sentence_index = if self.compress_postings {
+            PostingIndex::Compressed(CompressedMap::compressed_from_map(
+                self.term_to_sentence,
+                index_file,
+            )?)
+        } else {
+            PostingIndex::Raw(MultiMap::multi_from_map(self.term_to_sentence, index_file)?)
+        };
         let sentence_store: RkyvMap<SentenceId, Sentence<SM>> = RkyvMap::rkyv_from_map(
             self.sentence_map,
             open_mapfile(dir.join("sentences.storage.joie"))?,
@@ -136,9 +276,109 @@ where
                 doc_meta: metadata_store,
                 sentences: sentence_store,
                 index: sentence_index,
+                stats: self.stats,
             },
             documents: doc_store,
+            completions: completions.build(&self.term_map.analyzer),
             term_map: self.term_map.freeze(),
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        builder::{DatabaseBuilder, DocumentData},
+        test::database,
+        Database,
+    };
+
+    fn matching(db: &Database<String, u32, ()>, query: &str) -> Vec<u32> {
+        let query = db.parse_query(query, (), true).unwrap();
+        db.query(&query).map(|result| result.id.doc).collect()
+    }
+
+    #[test]
+    fn test_exact_terms() {
+        let docs = ["The dragons sleep.", "A dragon sleeps in a tower."];
+        let db = database("builder_exact_terms", &docs, |_| {});
+
+        assert_eq!(matching(&db, "dragon"), [1, 2]);
+        assert_eq!(matching(&db, "=dragon"), [2]);
+        assert_eq!(matching(&db, "=dragons"), [1]);
+        assert_eq!(matching(&db, "=\"dragon sleeps\""), [2]);
+        assert_eq!(matching(&db, "=\"dragon sleep\""), Vec::<u32>::new());
+
+        // a stem with only one form shares its postings with it
+        let terms = &db.term_map;
+        assert_eq!(terms.exact_term("tower"), terms.term("tower"));
+        assert_ne!(terms.exact_term("dragon"), terms.term("dragon"));
+        assert_eq!(matching(&db, "=tower"), [2]);
+    }
+
+    #[test]
+    fn test_without_exact_postings() {
+        let docs = ["The dragons sleep.", "A dragon sleeps."];
+        let db = database("builder_stemmed_only", &docs, |builder| {
+            builder.set_exact_postings(false)
+        });
+
+        assert_eq!(db.term_map.exact_term("dragon"), db.term_map.term("dragon"));
+        assert_eq!(matching(&db, "=dragon"), [1, 2]);
+        assert_eq!(matching(&db, "=\"dragon sleeps\""), [1, 2]);
+    }
+
+    #[test]
+    fn test_stopwords_by_exact_form() {
+        let docs = ["They do it.", "Doing it now.", "It is done."];
+        let db = database("builder_stopword_forms", &docs, |builder| {
+            builder.set_stopwords(["do"])
+        });
+
+        // doing shares its stem with the stopword, but isn't one
+        assert_eq!(matching(&db, "=doing"), [2]);
+        assert_eq!(matching(&db, "doing"), [1, 2]);
+        assert_eq!(matching(&db, "=do"), [1]);
+    }
+
+    #[test]
+    fn test_stopword_only_queries() {
+        let docs = ["The tower of the wizard.", "A tower.", "Of course."];
+        let db = database("builder_stopword_only", &docs, |builder| {
+            builder.set_stopwords(["the", "of", "a"])
+        });
+
+        assert_eq!(matching(&db, "the"), [1]);
+        assert_eq!(matching(&db, "of the"), [1]);
+        assert_eq!(matching(&db, "of"), [1, 3]);
+        assert_eq!(matching(&db, "the tower"), [1]);
+        assert_eq!(matching(&db, "\"the tower\" AND \"of the\""), [1]);
+    }
+
+    #[test]
+    fn test_stopwords_set_late() {
+        let dir = std::env::temp_dir().join("joie_builder_stopwords_late.test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut builder: DatabaseBuilder<String, u32, ()> = DatabaseBuilder::default();
+        for (id, text) in [(1, "The dragon sleeps."), (2, "The dragon flies.")] {
+            builder.add_document(DocumentData {
+                id,
+                text,
+                metadata: 0,
+                data: text.to_string(),
+            });
+
+            // applies to the document added before it too
+            builder.set_stopwords(["the"]);
+        }
+
+        let db = builder.build_in(&dir).unwrap();
+        let the = db.term_map.exact_term("the").unwrap();
+
+        assert!(db.search.index.get(&the).is_none());
+        assert_eq!(matching(&db, "the dragon"), [1, 2]);
+        assert_eq!(matching(&db, "=the"), [1, 2]);
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::{HashMap, HashSet};
+
+use smartstring::alias::CompactString;
+
+use crate::{analyzer::Analyzer, sentence::Sentence};
+
+// phrases seen fewer times than this aren't worth suggesting
+const MIN_PHRASE_COUNT: u64 = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "persistence", derive(serde::Serialize, serde::Deserialize))]
+pub struct Completion {
+    // the most common spelling, as written in the documents
+    pub text: CompactString,
+    // how many times it occurs
+    pub frequency: u64,
+    // whether this is a two word phrase rather than a single word
+    pub phrase: bool,
+}
+
+// counts words and adjacent word pairs while documents are added
+#[derive(Default, Debug)]
+pub(crate) struct CompletionBuilder {
+    words: HashMap<CompactString, u64>,
+    phrases: HashMap<CompactString, u64>,
+}
+
+impl CompletionBuilder {
+    pub fn add_sentence<M>(&mut self, sentence: &Sentence<M>, stopwords: &HashSet<u32>) {
+        let words: Vec<&str> = sentence
+            .tokens
+            .iter()
+            .map(|token| &sentence.text[token.start..token.end])
+            .collect();
+
+        // stopwords have no postings, so neither they nor phrases with them would find anything
+        let is_stopword = |idx: usize| stopwords.contains(&sentence.exact_terms[idx]);
+
+        for (idx, word) in words.iter().enumerate() {
+            if !is_stopword(idx) {
+                *self.words.entry((*word).into()).or_default() += 1;
+            }
+
+            if idx == 0 {
+                continue;
+            }
+
+            let (prev, current) = (&sentence.tokens[idx - 1], &sentence.tokens[idx]);
+            let joined_by_space = sentence.text[prev.end..current.start]
+                .chars()
+                .all(char::is_whitespace);
+
+            if joined_by_space && !is_stopword(idx - 1) && !is_stopword(idx) {
+                let phrase: CompactString = [words[idx - 1], *word].join(" ").into();
+                *self.phrases.entry(phrase).or_default() += 1;
+            }
+        }
+    }
+
+    pub fn build(self, analyzer: &impl Analyzer) -> Completions {
+        // normalized key -> (completion, count of its current spelling)
+        let mut grouped: HashMap<CompactString, (Completion, u64)> = HashMap::new();
+
+        let phrases = self
+            .phrases
+            .into_iter()
+            .map(|(text, count)| (text, count, true));
+
+        for (text, count, phrase) in self
+            .words
+            .into_iter()
+            .map(|(text, count)| (text, count, false))
+            .chain(phrases)
+        {
+            let key: CompactString = analyzer.normalize(&text).as_ref().into();
+            let (completion, best) = grouped.entry(key).or_insert_with(|| {
+                (
+                    Completion {
+                        text: text.clone(),
+                        frequency: 0,
+                        phrase,
+                    },
+                    count,
+                )
+            });
+
+            completion.frequency += count;
+            // ties go to the lowest spelling, so builds are reproducible
+            if count > *best || (count == *best && text < completion.text) {
+                completion.text = text;
+                *best = count;
+            }
+        }
+
+        let mut entries: Vec<(CompactString, Completion)> = grouped
+            .into_iter()
+            .map(|(key, (completion, _))| (key, completion))
+            .filter(|(_, completion)| {
+                !completion.phrase || completion.frequency >= MIN_PHRASE_COUNT
+            })
+            .collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        Completions { entries }
+    }
+}
+
+// words and frequent phrases, sorted by their normalized form
+#[derive(Default, Clone, Debug)]
+#[cfg_attr(feature = "persistence", derive(serde::Serialize, serde::Deserialize))]
+pub struct Completions {
+    entries: Vec<(CompactString, Completion)>,
+}
+
+impl Completions {
+    // the `limit` most frequent words and phrases starting with `prefix`, which should be
+    // normalized by the index's analyzer
+    pub fn suggest(&self, prefix: &str, limit: usize) -> Vec<&Completion> {
+        let start = self
+            .entries
+            .partition_point(|(key, _)| key.as_str() < prefix);
+        let len = self.entries[start..].partition_point(|(key, _)| key.starts_with(prefix));
+
+        let mut matches: Vec<&Completion> = self.entries[start..start + len]
+            .iter()
+            .map(|(_, completion)| completion)
+            .collect();
+
+        matches.sort_by(|a, b| b.frequency.cmp(&a.frequency).then(a.text.cmp(&b.text)));
+        matches.truncate(limit);
+        matches
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::database;
+
+    #[test]
+    fn test_stopwords_not_suggested() {
+        let db = database(
+            "completion_stopwords",
+            &["The theory of the theme.\nThe theory holds."],
+            |builder| builder.set_stopwords(["the"]),
+        );
+
+        let suggestions: Vec<&str> = db
+            .suggest("the", 10)
+            .iter()
+            .map(|completion| completion.text.as_str())
+            .collect();
+
+        assert_eq!(suggestions, ["theory", "theme"]);
+    }
+}
@@ -0,0 +1,216 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use smartstring::alias::CompactString;
+
+// expansions stop after this many terms, unless asked otherwise
+pub const DEFAULT_EXPANSION_LIMIT: usize = 128;
+
+// every unstemmed term in the index, sorted, for lookups that a perfect map can't do
+#[derive(Default, Clone, Debug)]
+#[cfg_attr(feature = "persistence", derive(serde::Serialize, serde::Deserialize))]
+pub struct TermDictionary {
+    terms: Vec<(CompactString, u32)>,
+}
+
+// the term ids a pattern expanded to
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+pub struct Expansion {
+    pub terms: Vec<u32>,
+    // whether more terms matched, but got cut off by the expansion limit
+    pub truncated: bool,
+}
+
+impl TermDictionary {
+    pub fn from_map(map: &HashMap<CompactString, u32>) -> TermDictionary {
+        let mut terms: Vec<(CompactString, u32)> =
+            map.iter().map(|(term, id)| (term.clone(), *id)).collect();
+        terms.sort_unstable();
+
+        TermDictionary { terms }
+    }
+
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.terms.iter().map(|(term, id)| (term.as_str(), *id))
+    }
+
+    // terms starting with `prefix`, in order
+    pub fn prefix<'a>(&'a self, prefix: &str) -> impl Iterator<Item = (&'a str, u32)> {
+        let start = self
+            .terms
+            .partition_point(|(term, _)| term.as_str() < prefix);
+        let len = self.terms[start..].partition_point(|(term, _)| term.starts_with(prefix));
+
+        self.terms[start..start + len]
+            .iter()
+            .map(|(term, id)| (term.as_str(), *id))
+    }
+
+    // terms matching a pattern where `*` stands for any run of characters and `?` for any one
+    pub fn wildcard<'a>(&'a self, pattern: &'a str) -> impl Iterator<Item = (&'a str, u32)> + 'a {
+        // everything before the first wildcard narrows down the range to check
+        let literal = &pattern[..pattern.find(['*', '?']).unwrap_or(pattern.len())];
+
+        self.prefix(literal)
+            .filter(move |(term, _)| wildcard_match(pattern, term))
+    }
+
+    // terms within `max_distance` edits of `term`, closest first
+    pub fn fuzzy<'a>(&'a self, term: &str, max_distance: usize) -> Vec<(&'a str, u32, usize)> {
+        let term: Vec<char> = term.chars().collect();
+        let mut candidate = Vec::new();
+
+        let mut matches: Vec<(&str, u32, usize)> = self
+            .iter()
+            .filter_map(|(text, id)| {
+                candidate.clear();
+                candidate.extend(text.chars());
+
+                let distance = edit_distance(&term, &candidate, max_distance)?;
+                Some((text, id, distance))
+            })
+            .collect();
+
+        matches.sort_by_key(|(_, _, distance)| *distance);
+        matches
+    }
+}
+
+impl Expansion {
+    pub fn collect<'a>(terms: impl Iterator<Item = (&'a str, u32)>, limit: usize) -> Expansion {
+        let mut terms = terms.map(|(_, id)| id);
+        let expanded: Vec<u32> = terms.by_ref().take(limit).collect();
+
+        Expansion {
+            terms: expanded,
+            truncated: terms.next().is_some(),
+        }
+    }
+}
+
+// levenshtein distance between a and b, or None if it's over `max`
+pub fn edit_distance(a: &[char], b: &[char], max: usize) -> Option<usize> {
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        let mut row_min = current[0];
+
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(prev[j + 1] + 1).min(current[j] + 1);
+            row_min = row_min.min(current[j + 1]);
+        }
+
+        // every later row is at least this far off
+        if row_min > max {
+            return None;
+        }
+
+        std::mem::swap(&mut prev, &mut current);
+    }
+
+    Some(prev[b.len()]).filter(|distance| *distance <= max)
+}
+
+pub fn is_wildcard_pattern(text: &str) -> bool {
+    text.contains(['*', '?'])
+}
+
+// the pattern a query word stands for, if it is one. trailing question marks end a sentence
+// rather than match a character, unless they're escaped: "wizard?" is a word, "wizard\?" a pattern
+pub fn word_pattern(word: &str) -> Option<Cow<'_, str>> {
+    let mut core = word;
+    while let Some(rest) = core.strip_suffix('?') {
+        if rest.ends_with('\\') {
+            break;
+        }
+        core = rest;
+    }
+
+    if !is_wildcard_pattern(core) {
+        None
+    } else if core.contains("\\?") {
+        Some(Cow::Owned(core.replace("\\?", "?")))
+    } else {
+        Some(Cow::Borrowed(core))
+    }
+}
+
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), text.chars().collect());
+    let (mut p, mut t) = (0, 0);
+    // where the last * was, and the text position it's currently matched up to
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                t += 1;
+            }
+            Some(c) if *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // let the last * swallow one more character
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    p = star + 1;
+                    t = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dictionary::{edit_distance, wildcard_match};
+
+    fn distance(a: &str, b: &str, max: usize) -> Option<usize> {
+        let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+        edit_distance(&a, &b, max)
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(distance("wizard", "wizard", 0), Some(0));
+        assert_eq!(distance("wizzard", "wizard", 2), Some(1));
+        assert_eq!(distance("dargon", "dragon", 2), Some(2));
+        assert_eq!(distance("dargon", "dragon", 1), None);
+        assert_eq!(distance("", "abc", 3), Some(3));
+        assert_eq!(distance("naïve", "naive", 1), Some(1));
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("drag*", "dragon"));
+        assert!(wildcard_match("w?z*rd", "wizard"));
+        assert!(wildcard_match("*er", "tower"));
+        assert!(wildcard_match("a*b*c", "aXbYbc"));
+        assert!(!wildcard_match("w?z*rd", "wzard"));
+        assert!(!wildcard_match("drag", "dragon"));
+    }
+}
@@ -1,35 +1,55 @@
-use crate::sentence::*;
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::{sentence::*, Token};
 
 use rkyv::Archive;
+use unicode_segmentation::UnicodeSegmentation;
 
 pub trait Highlighter<'a> {
     fn highlight<'b, S: Archive>(&'a self, sentence: &'b ArchivedSentence<S>)
         -> Vec<SentenceRange>;
 }
 
-#[inline(always)]
+// takes ranges sorted by start. overlapping ranges of the same class get merged, while ranges of
+// different classes get split: where they overlap, the one starting later (usually the more
+// specific match) wins
 pub fn collapse_overlapped_ranges(ranges: &[SentenceRange]) -> Vec<SentenceRange> {
-    let mut result = Vec::with_capacity(ranges.len());
-    let mut ranges_it = ranges.iter();
+    let mut bounds: Vec<usize> = ranges.iter().flat_map(|r| [r.start, r.end]).collect();
+    bounds.sort_unstable();
+    bounds.dedup();
 
-    let mut current = match ranges_it.next() {
-        Some(range) => *range,
-        None => return result,
-    };
+    let mut result: Vec<SentenceRange> = Vec::with_capacity(ranges.len());
 
-    for range in ranges {
-        if current.end > range.start {
-            current = SentenceRange {
-                start: current.start,
-                end: std::cmp::max(current.end, range.end),
-            };
-        } else {
-            result.push(current);
-            current = *range;
+    // ranges that started so far, the latest start and then the lowest class on top
+    let mut active = BinaryHeap::with_capacity(ranges.len());
+    let mut next = 0;
+
+    for window in bounds.windows(2) {
+        let (start, end) = (window[0], window[1]);
+
+        while let Some(range) = ranges.get(next).filter(|range| range.start <= start) {
+            active.push((range.start, Reverse(range.class), range.end));
+            next += 1;
+        }
+
+        // ranges that ended before this stretch only get dropped once they're on top
+        while active
+            .peek()
+            .is_some_and(|&(_, _, range_end)| range_end <= start)
+        {
+            active.pop();
+        }
+
+        let Some(&(_, Reverse(class), _)) = active.peek() else {
+            continue;
+        };
+
+        match result.last_mut() {
+            Some(last) if last.end == start && last.class == class => last.end = end,
+            _ => result.push(SentenceRange { start, end, class }),
         }
     }
 
-    result.push(current);
     result
 }
 
@@ -37,12 +57,12 @@ pub fn highlight_by_ranges<'a>(ranges: &[SentenceRange], text: &'a str) -> Vec<S
     let mut cursor = 0;
     let mut results = Vec::with_capacity(ranges.len() * 2);
 
-    for SentenceRange { start, end } in ranges.iter().copied() {
+    for SentenceRange { start, end, class } in ranges.iter().copied() {
         if cursor < start {
             results.push(SentencePart::Normal(&text[cursor..start]));
         }
 
-        results.push(SentencePart::Highlight(&text[start..end]));
+        results.push(SentencePart::Highlight(&text[start..end], class));
 
         cursor = end;
     }
@@ -53,3 +73,339 @@ pub fn highlight_by_ranges<'a>(ranges: &[SentenceRange], text: &'a str) -> Vec<S
 
     results
 }
+
+// what highlight offsets count
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OffsetUnit {
+    Bytes,
+    Chars,
+    // utf-16 code units, like javascript string indices
+    Utf16,
+}
+
+impl OffsetUnit {
+    #[inline(always)]
+    fn len(&self, text: &str) -> usize {
+        match self {
+            OffsetUnit::Bytes => text.len(),
+            OffsetUnit::Chars => text.chars().count(),
+            OffsetUnit::Utf16 => text.chars().map(char::len_utf16).sum(),
+        }
+    }
+}
+
+// byte ranges into `text`, converted to `unit`. the text is only scanned once, up to the last
+// range's end
+pub fn convert_ranges(
+    ranges: &[SentenceRange],
+    text: &str,
+    unit: OffsetUnit,
+) -> Vec<SentenceRange> {
+    if unit == OffsetUnit::Bytes {
+        return ranges.to_vec();
+    }
+
+    let mut bounds: Vec<usize> = ranges.iter().flat_map(|r| [r.start, r.end]).collect();
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    // the converted offset of each bound
+    let mut converted = Vec::with_capacity(bounds.len());
+    let (mut cursor, mut offset) = (0, 0);
+    for bound in &bounds {
+        offset += unit.len(&text[cursor..*bound]);
+        converted.push(offset);
+        cursor = *bound;
+    }
+
+    let convert = |pos: usize| converted[bounds.binary_search(&pos).unwrap()];
+
+    ranges
+        .iter()
+        .map(|range| SentenceRange {
+            start: convert(range.start),
+            end: convert(range.end),
+            class: range.class,
+        })
+        .collect()
+}
+
+// how much of a sentence a snippet may show
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SnippetBudget {
+    Chars(usize),
+    Tokens(usize),
+}
+
+// like highlight_by_ranges, but trimmed to `budget` around the densest cluster of highlights.
+// cuts fall on token boundaries where possible, and on grapheme boundaries otherwise
+pub fn snippet_by_ranges<'a>(
+    ranges: &[SentenceRange],
+    tokens: &[Token],
+    text: &'a str,
+    budget: SnippetBudget,
+) -> Vec<SentencePart<'a>> {
+    let (limit, fits) = match budget {
+        SnippetBudget::Chars(n) => (n, text.chars().count() <= n),
+        SnippetBudget::Tokens(n) => (n, tokens.len() <= n),
+    };
+
+    if fits || tokens.is_empty() {
+        return highlight_by_ranges(ranges, text);
+    }
+
+    // char offset of each token's start and end
+    let mut char_offsets = Vec::with_capacity(tokens.len());
+    let (mut cursor, mut chars) = (0, 0);
+    for token in tokens {
+        chars += text[cursor..token.start].chars().count();
+        let start = chars;
+        chars += text[token.start..token.end].chars().count();
+        char_offsets.push((start, chars));
+        cursor = token.end;
+    }
+
+    // size of the tokens a..b. text before the first token or after the last one is shown along
+    // with it, so it counts too
+    let total_chars = text.chars().count();
+    let cost = |a: usize, b: usize| match budget {
+        SnippetBudget::Chars(_) => {
+            let start = if a == 0 { 0 } else { char_offsets[a].0 };
+            let end = if b == tokens.len() {
+                total_chars
+            } else {
+                char_offsets[b - 1].1
+            };
+            end - start
+        }
+        SnippetBudget::Tokens(_) => b - a,
+    };
+
+    // the tokens covered by each highlight
+    let spans: Vec<(usize, usize)> = ranges
+        .iter()
+        .map(|range| {
+            (
+                tokens.partition_point(|t| t.end <= range.start),
+                tokens.partition_point(|t| t.start < range.end),
+            )
+        })
+        .filter(|(first, last)| first < last)
+        .collect();
+
+    // the run of highlights with the most members that fits in the budget
+    let (mut a, mut b) = spans.first().copied().unwrap_or((0, 1));
+    let mut best = 0;
+    let mut j = 0;
+    for i in 0..spans.len() {
+        j = std::cmp::max(i, j);
+        while j < spans.len() && cost(spans[i].0, spans[j].1) <= limit {
+            j += 1;
+        }
+
+        if j - i > best {
+            best = j - i;
+            (a, b) = (spans[i].0, spans[j - 1].1);
+        }
+    }
+
+    // fill whatever budget is left with context, on both sides
+    loop {
+        let mut grew = false;
+
+        if b < tokens.len() && cost(a, b + 1) <= limit {
+            b += 1;
+            grew = true;
+        }
+
+        if a > 0 && cost(a - 1, b) <= limit {
+            a -= 1;
+            grew = true;
+        }
+
+        if !grew {
+            break;
+        }
+    }
+
+    let start = if a == 0 { 0 } else { tokens[a].start };
+    let mut end = if b == tokens.len() {
+        text.len()
+    } else {
+        tokens[b - 1].end
+    };
+
+    // a single highlight can be too big by itself, so it gets cut short
+    if cost(a, b) > limit {
+        end = match budget {
+            SnippetBudget::Chars(_) => text[start..end]
+                .grapheme_indices(true)
+                .scan(0, |chars, (idx, grapheme)| {
+                    *chars += grapheme.chars().count();
+                    (*chars <= limit).then_some(start + idx + grapheme.len())
+                })
+                .last()
+                .unwrap_or(start),
+            SnippetBudget::Tokens(_) => tokens[a + std::cmp::max(limit, 1) - 1].end,
+        };
+    }
+
+    let window_ranges: Vec<SentenceRange> = ranges
+        .iter()
+        .filter(|range| range.end > start && range.start < end)
+        .map(|range| SentenceRange {
+            start: std::cmp::max(range.start, start) - start,
+            end: std::cmp::min(range.end, end) - start,
+            class: range.class,
+        })
+        .collect();
+
+    let mut parts = Vec::with_capacity(window_ranges.len() * 2 + 2);
+
+    if start > 0 {
+        parts.push(SentencePart::Ellipsis);
+    }
+
+    parts.extend(highlight_by_ranges(&window_ranges, &text[start..end]));
+
+    if end < text.len() {
+        parts.push(SentencePart::Ellipsis);
+    }
+
+    parts
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        highlight::{
+            collapse_overlapped_ranges, convert_ranges, snippet_by_ranges, OffsetUnit,
+            SnippetBudget,
+        },
+        sentence::{SentencePart, SentenceRange},
+        test::render,
+        Token,
+    };
+
+    fn range(start: usize, end: usize, class: u32) -> SentenceRange {
+        SentenceRange { start, end, class }
+    }
+
+    #[test]
+    fn test_convert_ranges() {
+        // ö is two bytes, the dragon four bytes and two utf-16 units
+        let text = "Nöthing 🐉 here";
+        let ranges = [range(0, 8, 0), range(9, 13, 1), range(14, 18, 0)];
+
+        assert_eq!(convert_ranges(&ranges, text, OffsetUnit::Bytes), ranges);
+        assert_eq!(
+            convert_ranges(&ranges, text, OffsetUnit::Chars),
+            [range(0, 7, 0), range(8, 9, 1), range(10, 14, 0)]
+        );
+        assert_eq!(
+            convert_ranges(&ranges, text, OffsetUnit::Utf16),
+            [range(0, 7, 0), range(8, 10, 1), range(11, 15, 0)]
+        );
+    }
+
+    #[test]
+    fn test_collapse_overlapped_ranges() {
+        let ranges = [
+            // a later, nested range of another class wins where they overlap
+            range(0, 10, 1),
+            range(4, 6, 0),
+            // with the same start, the lower class wins
+            range(12, 16, 2),
+            range(12, 14, 1),
+            // the same class just merges
+            range(20, 25, 0),
+            range(22, 28, 0),
+            // once a nested range ends, the one it was nested in takes over again
+            range(30, 40, 2),
+            range(31, 39, 1),
+            range(32, 34, 0),
+        ];
+
+        assert_eq!(
+            collapse_overlapped_ranges(&ranges),
+            [
+                range(0, 4, 1),
+                range(4, 6, 0),
+                range(6, 10, 1),
+                range(12, 14, 1),
+                range(14, 16, 2),
+                range(20, 28, 0),
+                range(30, 31, 2),
+                range(31, 32, 1),
+                range(32, 34, 0),
+                range(34, 39, 1),
+                range(39, 40, 2),
+            ]
+        );
+    }
+
+    fn tokens(text: &str) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        let mut start = None;
+
+        for (idx, c) in text.char_indices().chain([(text.len(), ' ')]) {
+            match (start, c.is_alphanumeric()) {
+                (None, true) => start = Some(idx),
+                (Some(s), false) => {
+                    tokens.push(Token { start: s, end: idx });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+
+        tokens
+    }
+
+    fn snippet(text: &str, highlight: &str, chars: usize) -> String {
+        let start = text.find(highlight).unwrap();
+        let ranges = [SentenceRange {
+            start,
+            end: start + highlight.len(),
+            class: 0,
+        }];
+        let parts = snippet_by_ranges(&ranges, &tokens(text), text, SnippetBudget::Chars(chars));
+
+        let shown: usize = parts
+            .iter()
+            .map(|part| match part {
+                SentencePart::Normal(v) | SentencePart::Highlight(v, _) => v.chars().count(),
+                SentencePart::Ellipsis => 0,
+            })
+            .sum();
+        assert!(
+            shown <= chars,
+            "{shown} chars shown with a budget of {chars}"
+        );
+
+        render(&parts)
+    }
+
+    #[test]
+    fn test_snippet_budget_at_edges() {
+        // the text before the first token counts against the budget
+        assert_eq!(
+            snippet("«« dragon sleeps now", "dragon", 14),
+            "«« [dragon|0]..."
+        );
+        assert_eq!(
+            snippet("«« dragon sleeps now", "dragon", 16),
+            "«« [dragon|0] sleeps..."
+        );
+
+        // and so does the text after the last one
+        assert_eq!(
+            snippet("now sleeps dragon »»", "dragon", 14),
+            "...[dragon|0] »»"
+        );
+        assert_eq!(
+            snippet("now sleeps dragon »»", "dragon", 16),
+            "...sleeps [dragon|0] »»"
+        );
+    }
+}
@@ -1,10 +1,21 @@
 use bytemuck::Zeroable;
 use rayon::prelude::{IntoParallelRefMutIterator, ParallelIterator};
+use storage::{Compressible, Storage};
 
-use crate::sentence::SentenceId;
+use crate::{
+    postings::Postings, query::DocumentFilter, searcher::SearchEngine, sentence::SentenceId,
+    DocumentMetadata, SentenceMetadata,
+};
 
 const PARALLEL_MERGE_THRESH: usize = 32768;
 
+// lists closer in length than this are intersected block by block, otherwise the shorter one
+// gallops through the longer one
+const GALLOP_RATIO: usize = 16;
+
+// ids compared at once when intersecting blocks
+const INTERSECT_BLOCK: usize = 8;
+
 pub struct SentenceIdList {
     pub(crate) ids: Vec<SentenceId>,
 }
@@ -54,6 +65,151 @@ impl SentenceIdList {
             }
         })
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    // drops the ids of documents the filter rejects
+    pub(crate) fn filter_documents<D, S, DF>(&mut self, filter: &DF, db: &SearchEngine<D, S>)
+    where
+        D: DocumentMetadata,
+        S: SentenceMetadata,
+        DF: DocumentFilter<D>,
+    {
+        if !DF::needed() {
+            return;
+        }
+
+        match filter.document_bitmap() {
+            Some(allowed) => self
+                .ids
+                .retain(|id| id.is_valid() && allowed.contains(id.doc)),
+            None => self.retain(|id| {
+                filter.filter_document_id(id.doc, unsafe {
+                    db.doc_meta.get_unchecked(id.doc as usize)
+                })
+            }),
+        }
+    }
+
+    // keeps only the ids that are also in `other`. both lists have to be sorted, and invalid ids
+    // (which sort first) are dropped
+    pub fn intersect(&mut self, other: &[SentenceId]) {
+        let (a, b) = (valid_ids(&self.ids), valid_ids(other));
+        let mut out = Vec::with_capacity(a.len().min(b.len()));
+
+        if a.len().saturating_mul(GALLOP_RATIO) < b.len() {
+            gallop_intersect(a, b, &mut out);
+        } else if b.len().saturating_mul(GALLOP_RATIO) < a.len() {
+            gallop_intersect(b, a, &mut out);
+        } else {
+            block_intersect(a, b, &mut out);
+        }
+
+        self.ids = out;
+    }
+
+    // like intersect, but compressed postings are walked with their skip pointers rather than
+    // decoded
+    pub(crate) fn intersect_postings(&mut self, other: Postings) {
+        match other {
+            Postings::Raw(ids) => self.intersect(ids),
+            Postings::Compressed(list) => {
+                let mut cursor = list.cursor();
+                self.ids
+                    .retain(|id| id.is_valid() && cursor.seek(*id) == Some(*id));
+            }
+        }
+    }
+
+    // drops the ids that are in `other`. both lists have to be sorted
+    pub fn subtract(&mut self, other: &[SentenceId]) {
+        let mut rest = valid_ids(other);
+
+        self.ids.retain(|id| {
+            if !id.is_valid() {
+                return false;
+            }
+
+            rest = &rest[gallop(rest, |v| v < id)..];
+            rest.first() != Some(id)
+        });
+    }
+}
+
+fn valid_ids(ids: &[SentenceId]) -> &[SentenceId] {
+    &ids[ids.partition_point(|id| !id.is_valid())..]
+}
+
+// like partition_point, but tries exponentially growing steps from the start first, so it's
+// cheap when the partition point is close
+pub fn gallop<T>(set: &[T], mut pred: impl FnMut(&T) -> bool) -> usize {
+    let mut lo = 0;
+    let mut step = 1;
+
+    while lo + step < set.len() && pred(&set[lo + step]) {
+        lo += step;
+        step *= 2;
+    }
+
+    let hi = set.len().min(lo + step + 1);
+    lo + set[lo..hi].partition_point(pred)
+}
+
+// for each id of the short list, gallops to where it would be in the long one
+fn gallop_intersect(short: &[SentenceId], long: &[SentenceId], out: &mut Vec<SentenceId>) {
+    let mut rest = long;
+
+    for id in short {
+        rest = &rest[gallop(rest, |v| v < id)..];
+
+        match rest.first() {
+            Some(v) if v == id => out.push(*id),
+            Some(_) => {}
+            None => break,
+        }
+    }
+}
+
+// compares whole blocks against each other without branching, so the compiler can vectorize
+// it, then moves past whichever block ends first
+fn block_intersect(a: &[SentenceId], b: &[SentenceId], out: &mut Vec<SentenceId>) {
+    let (mut i, mut j) = (0, 0);
+
+    while i + INTERSECT_BLOCK <= a.len() && j + INTERSECT_BLOCK <= b.len() {
+        let block_a = &a[i..i + INTERSECT_BLOCK];
+        let mut block_b = [0u64; INTERSECT_BLOCK];
+        for (key, id) in block_b.iter_mut().zip(&b[j..j + INTERSECT_BLOCK]) {
+            *key = id.to_u64();
+        }
+
+        for id in block_a {
+            let key = id.to_u64();
+            if block_b.iter().fold(false, |found, v| found | (*v == key)) {
+                out.push(*id);
+            }
+        }
+
+        let (last_a, last_b) = (block_a[INTERSECT_BLOCK - 1], b[j + INTERSECT_BLOCK - 1]);
+        if last_a <= last_b {
+            i += INTERSECT_BLOCK;
+        }
+        if last_b <= last_a {
+            j += INTERSECT_BLOCK;
+        }
+    }
+
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                out.push(a[i]);
+                i += 1;
+            }
+        }
+    }
 }
 
 impl IntoIterator for SentenceIdList {
@@ -170,7 +326,14 @@ pub fn scalar_merge<T: Ord + Copy>(a: &[T], b: &[T], out: &mut [T]) {
 mod test {
     use std::iter::repeat_with;
 
-    use crate::{id_list::SentenceIdList, sentence::SentenceId};
+    use bytemuck::Zeroable;
+
+    use crate::{
+        id_list::{gallop, SentenceIdList},
+        query::DocumentBitmap,
+        sentence::SentenceId,
+        test::database,
+    };
 
     fn gen_list(n: usize) -> Vec<SentenceId> {
         let mut l: Vec<SentenceId> = repeat_with(|| SentenceId {
@@ -204,6 +367,77 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_gallop() {
+        let set: Vec<u32> = (0..100).map(|v| v * 2).collect();
+
+        for target in 0..210 {
+            assert_eq!(
+                gallop(&set, |v| *v < target),
+                set.partition_point(|v| *v < target)
+            );
+        }
+    }
+
+    #[test]
+    fn test_intersect() {
+        for (a, b) in [(0, 500), (500, 0), (10, 5000), (5000, 10), (3000, 4000)] {
+            for _ in 0..20 {
+                let (mut a, mut b) = (gen_list(a), gen_list(b));
+                a.dedup();
+                b.dedup();
+
+                let expected: Vec<SentenceId> = a
+                    .iter()
+                    .filter(|id| b.binary_search(id).is_ok())
+                    .copied()
+                    .collect();
+
+                let mut list = SentenceIdList { ids: a.clone() };
+                list.intersect(&b);
+                assert_eq!(list.ids, expected);
+
+                let expected: Vec<SentenceId> = a
+                    .iter()
+                    .filter(|id| b.binary_search(id).is_err())
+                    .copied()
+                    .collect();
+
+                let mut list = SentenceIdList { ids: a };
+                list.subtract(&b);
+                assert_eq!(list.ids, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_filter_documents() {
+        let db = database(
+            "id_list_filter",
+            &["One.", "Two.", "Three.", "Four."],
+            |_| {},
+        );
+        let mut ids: Vec<SentenceId> = (1..=4)
+            .flat_map(|doc| (0..3).map(move |sentence| SentenceId::new(doc, sentence)))
+            .collect();
+        ids.insert(0, SentenceId::zeroed());
+
+        let valid = |list: SentenceIdList| -> Vec<SentenceId> {
+            list.ids.into_iter().filter(|id| id.is_valid()).collect()
+        };
+
+        let mut by_bitmap = SentenceIdList { ids: ids.clone() };
+        by_bitmap.filter_documents(&DocumentBitmap::from_ids([2, 4, 7]), &db.search);
+
+        let mut by_id = SentenceIdList { ids };
+        by_id.filter_documents(&|meta: &u32| *meta == 20 || *meta == 40, &db.search);
+
+        let (by_bitmap, by_id) = (valid(by_bitmap), valid(by_id));
+        assert_eq!(by_bitmap, by_id);
+        assert!(by_bitmap.iter().all(|id| id.doc == 2 || id.doc == 4));
+        assert_eq!(by_bitmap.len(), 6);
+    }
+
     fn test_merge(a: Vec<SentenceId>, b: Vec<SentenceId>) {
         let par_merged = SentenceIdList::merge_slices(&a, &b);
 
@@ -5,26 +5,44 @@ use std::{
     path::Path,
 };
 
-use logos::Logos;
-use query::{parser::QueryToken, DocumentFilter, DynamicQuery, Query, QueryBuilder};
+use analyzer::{Analyzer, DefaultAnalyzer};
+use query::{
+    parser::QueryParseError, DocumentBitmap, DocumentFilter, DynamicQuery, ExpansionQuery, Query,
+    QueryBuilder,
+};
+use ranking::Bm25;
 use rkyv::Archive;
-use searcher::{SearchEngine, SearchResult};
+use searcher::{DocumentPage, Page, RankedDocument, SearchEngine, SearchResult, Snippet};
 
 use storage::{RkyvMap, SerializableToFile};
 
 #[cfg(feature = "persistence")]
-use crate::sentence::{Sentence, SentenceId};
+use crate::{
+    postings::PostingIndex,
+    ranking::CorpusStats,
+    sentence::{Sentence, SentenceId},
+};
 #[cfg(feature = "persistence")]
-use storage::{MultiMap, PersistentStorage, SimpleStorage};
+use storage::{CompressedMap, MultiMap, PersistentStorage, SimpleStorage};
 
+use completion::{Completion, Completions};
+use spelling::SpellCheck;
 use term_map::FrozenTermMap;
 
+pub mod analyzer;
 pub mod builder;
+pub mod completion;
+pub mod dictionary;
 pub mod highlight;
 mod id_list;
+mod postings;
 pub mod query;
+pub mod ranking;
+pub mod render;
 pub mod searcher;
+pub mod segment;
 pub mod sentence;
+pub mod spelling;
 pub mod term_map;
 
 pub trait DocumentMetadata: bytemuck::Pod + Default + Send + Sync {}
@@ -48,7 +66,7 @@ pub struct CopyableRange {
 
 pub type Token = CopyableRange;
 
-pub struct Database<Document, DM, SM>
+pub struct Database<Document, DM, SM, A = DefaultAnalyzer>
 where
     Document: Archive,
     DM: DocumentMetadata,
@@ -56,20 +74,32 @@ where
 {
     search: SearchEngine<DM, SM>,
     documents: RkyvMap<u32, Document>,
-    term_map: FrozenTermMap,
+    completions: Completions,
+    term_map: FrozenTermMap<A>,
 }
 
-impl<D, DM, SM> Database<D, DM, SM>
+impl<D, DM, SM, A> Database<D, DM, SM, A>
 where
     D: Archive,
     DM: DocumentMetadata,
     SM: SentenceMetadata + 'static,
+    A: Analyzer,
 {
+    #[inline(always)]
+    pub fn analyzer(&self) -> &A {
+        self.term_map.analyzer()
+    }
+
     #[inline(always)]
     pub fn tokenize_phrase(&self, query: &str) -> Vec<u32> {
         self.term_map.tokenize_phrase(query)
     }
 
+    #[inline(always)]
+    pub fn tokenize_exact_phrase(&self, query: &str) -> Vec<u32> {
+        self.term_map.tokenize_exact_phrase(query)
+    }
+
     #[inline(always)]
     pub fn query<'a>(
         &'a self,
@@ -78,6 +108,66 @@ where
         self.search.query(query)
     }
 
+    #[inline(always)]
+    pub fn query_page<'a>(
+        &'a self,
+        query: &'a (impl Query<DM, SM> + Send + Sync),
+        offset: usize,
+        limit: usize,
+    ) -> Page<'a, SM> {
+        self.search.query_page(query, offset, limit)
+    }
+
+    // one entry per matching document, paginated over documents rather than sentences
+    #[inline(always)]
+    pub fn query_documents<'a>(
+        &'a self,
+        query: &'a (impl Query<DM, SM> + Send + Sync),
+        offset: usize,
+        limit: usize,
+    ) -> DocumentPage<'a, D, SM> {
+        self.search
+            .query_documents(query, &self.documents, offset, limit)
+    }
+
+    // like query, but only the k best matching sentences, highest score first
+    #[inline(always)]
+    pub fn query_ranked<'a>(
+        &'a self,
+        query: &'a (impl Query<DM, SM> + Send + Sync),
+        k: usize,
+        bm25: &Bm25,
+    ) -> Vec<SearchResult<'a, SM>> {
+        self.search.query_ranked(query, k, bm25)
+    }
+
+    // each hit with up to `before` and `after` neighbouring sentences from its document,
+    // merging hits whose context overlaps into one snippet
+    #[inline(always)]
+    pub fn with_context<'a>(
+        &'a self,
+        results: impl IntoIterator<Item = SearchResult<'a, SM>>,
+        before: u32,
+        after: u32,
+    ) -> Vec<Snippet<'a, SM>> {
+        self.search.with_context(results, before, after)
+    }
+
+    #[inline(always)]
+    pub fn rank_documents(
+        &self,
+        query: &(impl Query<DM, SM> + Send + Sync),
+        k: usize,
+        bm25: &Bm25,
+    ) -> Vec<RankedDocument> {
+        self.search.rank_documents(query, k, bm25)
+    }
+
+    // the documents whose metadata matches, as a filter that can be reused across queries
+    pub fn document_bitmap(&self, predicate: impl Fn(&DM) -> bool) -> DocumentBitmap {
+        DocumentBitmap::from_predicate(&self.search, predicate)
+    }
+
     #[inline(always)]
     pub fn get_doc(&self, doc_id: &u32) -> Option<&<D as Archive>::Archived> {
         self.documents.get(doc_id)
@@ -88,13 +178,65 @@ where
         query: &str,
         document_filter: F,
         optimize: bool,
-    ) -> Option<DynamicQuery<DM, SM, F>> {
-        let tokens: Vec<QueryToken<'_>> = QueryToken::lexer(query)
-            .collect::<Result<Vec<QueryToken<'_>>, _>>()
-            .ok()?;
-        let expr = query::parser::query_grammar::expression(&tokens).ok()?;
+    ) -> Result<DynamicQuery<DM, SM, F>, QueryParseError> {
+        let expr = query::parser::parse_expression(query)?;
+
+        Ok(expr.parse(&self.term_map, document_filter, optimize))
+    }
+
+    // sentences containing any term starting with `prefix`, expanding to at most `limit` terms
+    pub fn prefix_query<F: DocumentFilter<DM>>(
+        &self,
+        prefix: &str,
+        document_filter: F,
+        limit: usize,
+    ) -> ExpansionQuery<DM, SM, F> {
+        let expansion = self.term_map.expand_prefix(prefix, limit);
+        QueryBuilder::start(&expansion.terms)
+            .filter_documents(document_filter)
+            .expanded(expansion.truncated)
+    }
+
+    // sentences containing any term matching a pattern, where `*` stands for any run of
+    // characters and `?` for any one, expanding to at most `limit` terms
+    pub fn wildcard_query<F: DocumentFilter<DM>>(
+        &self,
+        pattern: &str,
+        document_filter: F,
+        limit: usize,
+    ) -> ExpansionQuery<DM, SM, F> {
+        let expansion = self.term_map.expand_wildcard(pattern, limit);
+        QueryBuilder::start(&expansion.terms)
+            .filter_documents(document_filter)
+            .expanded(expansion.truncated)
+    }
+
+    // sentences containing any term within `max_distance` edits of `term`, expanding to at most
+    // `limit` of the closest terms
+    pub fn fuzzy_query<F: DocumentFilter<DM>>(
+        &self,
+        term: &str,
+        max_distance: usize,
+        document_filter: F,
+        limit: usize,
+    ) -> ExpansionQuery<DM, SM, F> {
+        let expansion = self.term_map.expand_fuzzy(term, max_distance, limit);
+        QueryBuilder::start(&expansion.terms)
+            .filter_documents(document_filter)
+            .expanded(expansion.truncated)
+    }
+
+    // the `limit` most frequent words and two word phrases starting with `prefix`, for
+    // search-as-you-type
+    pub fn suggest(&self, prefix: &str, limit: usize) -> Vec<&Completion> {
+        let prefix = self.term_map.analyzer().normalize(prefix);
+        self.completions.suggest(&prefix, limit)
+    }
 
-        Some(expr.parse(&self.term_map, document_filter, optimize))
+    // the query's words that aren't in the index, each with up to `limit` known terms it might
+    // have meant, closest and most common first
+    pub fn spell_check(&self, query: &str, limit: usize) -> SpellCheck {
+        spelling::check(&self.term_map, &self.search, query, limit)
     }
 
     pub fn phrase_query<F: DocumentFilter<DM> + Clone + 'static>(
@@ -111,11 +253,12 @@ where
 }
 
 #[cfg(feature = "persistence")]
-impl<D, DM, SM> Database<D, DM, SM>
+impl<D, DM, SM, A> Database<D, DM, SM, A>
 where
     D: Archive,
     DM: DocumentMetadata,
     SM: SentenceMetadata + 'static,
+    A: Analyzer + serde::Serialize + serde::de::DeserializeOwned,
 {
     pub fn persist(self, dir: impl AsRef<Path>) -> io::Result<()> {
         let dir = dir.as_ref();
@@ -135,36 +278,93 @@ where
             doc_meta,
             sentences,
             index,
+            stats,
         } = self.search;
 
+        write_ser(&stats, headers.join("stats.joie"))?;
+
         write_ser(&doc_meta.header(), headers.join("doc_meta.header.joie"))?;
         write_ser(
             &sentences.into_header(),
             headers.join("sentences.header.joie"),
         )?;
-        write_ser(
-            &index.into_header(),
-            headers.join("sentence_index.header.joie"),
-        )?;
+        // load goes by which header exists, so one left over from an earlier build has to go
+        let compressed_header = headers.join("sentence_index.compressed.header.joie");
+        match index {
+            PostingIndex::Raw(index) => {
+                if compressed_header.exists() {
+                    std::fs::remove_file(&compressed_header)?;
+                }
+
+                write_ser(
+                    &index.into_header(),
+                    headers.join("sentence_index.header.joie"),
+                )?
+            }
+            PostingIndex::Compressed(index) => write_ser(&index.into_header(), compressed_header)?,
+        }
         write_ser(
             &self.documents.into_header(),
             headers.join("documents.header.joie"),
         )?;
-        write_ser(&self.term_map, headers.join("term_map.joie"))?;
+        write_ser(&self.term_map.map, headers.join("term_map.joie"))?;
+        if let Some(exact) = &self.term_map.exact {
+            write_ser(exact, headers.join("exact_term_map.joie"))?;
+        }
+        write_ser(
+            &self.term_map.dictionary,
+            headers.join("term_dictionary.joie"),
+        )?;
+        write_ser(&self.term_map.analyzer, headers.join("analyzer.joie"))?;
+        write_ser(&self.completions, headers.join("completions.joie"))?;
 
         Ok(())
     }
 
-    pub fn load(dir: impl AsRef<Path>) -> io::Result<Database<D, DM, SM>> {
+    // like load, but refuses to open an index built with a different analyzer than the one given,
+    // since queries would then be analyzed differently from the documents they're matched against
+    pub fn load_with_analyzer(
+        dir: impl AsRef<Path>,
+        analyzer: A,
+    ) -> io::Result<Database<D, DM, SM, A>>
+    where
+        A: PartialEq + std::fmt::Debug,
+    {
+        let dir = dir.as_ref();
+
+        let index_analyzer: A =
+            postcard::from_bytes(&std::fs::read(dir.join("headers/analyzer.joie"))?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        if index_analyzer != analyzer {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("index was built with {index_analyzer:?}, but {analyzer:?} was requested"),
+            ));
+        }
+
+        Self::load(dir)
+    }
+
+    pub fn load(dir: impl AsRef<Path>) -> io::Result<Database<D, DM, SM, A>> {
         use std::fs;
 
         let dir = dir.as_ref();
         let headers = dir.join("headers/");
 
-        let sentence_index: MultiMap<u32, SentenceId> = MultiMap::load(
-            &fs::read(headers.join("sentence_index.header.joie"))?,
-            File::open(dir.join("sentences.index.joie"))?,
-        )?;
+        // whichever format the index was built with
+        let compressed_header = headers.join("sentence_index.compressed.header.joie");
+        let sentence_index = if compressed_header.exists() {
+            PostingIndex::Compressed(CompressedMap::load(
+                &fs::read(compressed_header)?,
+                File::open(dir.join("sentences.index.joie"))?,
+            )?)
+        } else {
+            PostingIndex::Raw(MultiMap::load(
+                &fs::read(headers.join("sentence_index.header.joie"))?,
+                File::open(dir.join("sentences.index.joie"))?,
+            )?)
+        };
 
         let sentence_store: RkyvMap<SentenceId, Sentence<SM>> = RkyvMap::load(
             &fs::read(headers.join("sentences.header.joie"))?,
@@ -185,8 +385,29 @@ where
             File::open(dir.join("documents.fast.joie"))?,
         )?;
 
-        let term_map: FrozenTermMap =
-            postcard::from_bytes(&fs::read(headers.join("term_map.joie"))?)
+        let term_map: FrozenTermMap<A> = FrozenTermMap {
+            map: postcard::from_bytes(&fs::read(headers.join("term_map.joie"))?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            // indexes from before exact terms existed match exact queries like stemmed ones
+            exact: match fs::read(headers.join("exact_term_map.joie")) {
+                Ok(bytes) => Some(
+                    postcard::from_bytes(&bytes)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+                ),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e),
+            },
+            dictionary: postcard::from_bytes(&fs::read(headers.join("term_dictionary.joie"))?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            analyzer: postcard::from_bytes(&fs::read(headers.join("analyzer.joie"))?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+        };
+
+        let stats: CorpusStats = postcard::from_bytes(&fs::read(headers.join("stats.joie"))?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let completions: Completions =
+            postcard::from_bytes(&fs::read(headers.join("completions.joie"))?)
                 .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
 
         Ok(Database {
@@ -194,9 +415,150 @@ where
                 doc_meta: metadata_store,
                 sentences: sentence_store,
                 index: sentence_index,
+                stats,
             },
             documents: doc_store,
+            completions,
             term_map,
         })
     }
 }
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::path::PathBuf;
+
+    use crate::{
+        analyzer::Analyzer,
+        builder::{DatabaseBuilder, DocumentData},
+        sentence::SentencePart,
+        Database,
+    };
+
+    // documents are numbered from 1, with ten times their id as metadata. `name` picks the
+    // directory the database is built in, so it has to be unique per test
+    pub(crate) fn database(
+        name: &str,
+        docs: &[&str],
+        configure: impl FnOnce(&mut DatabaseBuilder<String, u32, ()>),
+    ) -> Database<String, u32, ()> {
+        let mut builder = DatabaseBuilder::default();
+        configure(&mut builder);
+
+        database_with(name, docs, builder)
+    }
+
+    // like database, but with a builder that can use any analyzer
+    pub(crate) fn database_with<A: Analyzer>(
+        name: &str,
+        docs: &[&str],
+        mut builder: DatabaseBuilder<String, u32, (), A>,
+    ) -> Database<String, u32, (), A> {
+        for (idx, text) in docs.iter().enumerate() {
+            let id = idx as u32 + 1;
+            builder.add_document(DocumentData {
+                id,
+                text,
+                metadata: id * 10,
+                data: text.to_string(),
+            });
+        }
+
+        builder.build_in(dir(name)).unwrap()
+    }
+
+    // an empty directory for the test called `name`
+    pub(crate) fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("joie_{name}.test"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // highlights written inline as [text|class], and cut text as ...
+    pub(crate) fn render(parts: &[SentencePart]) -> String {
+        parts
+            .iter()
+            .map(|part| match part {
+                SentencePart::Normal(text) => text.to_string(),
+                SentencePart::Highlight(text, class) => format!("[{text}|{class}]"),
+                SentencePart::Ellipsis => "...".to_string(),
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "persistence")]
+    #[test]
+    fn test_load_without_exact_terms() {
+        let docs = ["The dragons sleep.", "A dragon sleeps."];
+        let db = database("lib_no_exact_terms", &docs, |_| {});
+        let dir = std::env::temp_dir().join("joie_lib_no_exact_terms.test");
+        db.persist(&dir).unwrap();
+
+        // as written before exact terms were indexed
+        std::fs::remove_file(dir.join("headers/exact_term_map.joie")).unwrap();
+
+        let db: Database<String, u32, ()> = Database::load(&dir).unwrap();
+        for query in ["=dragon", "=dragons"] {
+            let query = db.parse_query(query, (), true).unwrap();
+            let docs: Vec<u32> = db.query(&query).map(|result| result.id.doc).collect();
+            assert_eq!(docs, [1, 2]);
+        }
+    }
+
+    #[cfg(feature = "persistence")]
+    #[test]
+    fn test_analyzer_persisted() {
+        use crate::analyzer::{Lowercase, TextAnalyzer, Whitespace};
+
+        type Unstemmed = TextAnalyzer<Whitespace, Lowercase, ()>;
+        let analyzer: Unstemmed = TextAnalyzer::new(Whitespace, Lowercase, ());
+
+        let docs = ["Dragons, beware!", "A dragon."];
+        let builder = DatabaseBuilder::with_analyzer(analyzer.clone());
+        let db = database_with("lib_analyzer_persisted", &docs, builder);
+        let dir = std::env::temp_dir().join("joie_lib_analyzer_persisted.test");
+        db.persist(&dir).unwrap();
+
+        let db: Database<String, u32, (), Unstemmed> = Database::load(&dir).unwrap();
+        assert_eq!(db.analyzer(), &analyzer);
+
+        // queries are split on whitespace only and left unstemmed, like the documents were
+        let matching = |query: &str| -> Vec<u32> {
+            let query = db.parse_query(query, (), true).unwrap();
+            db.query(&query).map(|result| result.id.doc).collect()
+        };
+        assert_eq!(matching("dragons,"), [1]);
+        assert_eq!(matching("dragons"), Vec::<u32>::new());
+        assert_eq!(matching("dragon."), [2]);
+    }
+
+    #[cfg(feature = "persistence")]
+    #[test]
+    fn test_language_persisted() {
+        use rust_stemmers::Algorithm;
+
+        use crate::analyzer::DefaultAnalyzer;
+
+        let docs = ["Les chevaux vont manger.", "Un cheval mangeait."];
+        let builder = DatabaseBuilder::for_language(Some(Algorithm::French));
+        let db = database_with("lib_language_persisted", &docs, builder);
+        let dir = std::env::temp_dir().join("joie_lib_language_persisted.test");
+        db.persist(&dir).unwrap();
+
+        let english = DefaultAnalyzer::for_language(Some(Algorithm::English));
+        let mismatched = Database::<String, u32, ()>::load_with_analyzer(&dir, english);
+        assert_eq!(
+            mismatched.err().map(|e| e.kind()),
+            Some(std::io::ErrorKind::InvalidInput)
+        );
+
+        let french = DefaultAnalyzer::for_language(Some(Algorithm::French));
+        let db = Database::<String, u32, ()>::load_with_analyzer(&dir, french).unwrap();
+        assert_eq!(db.analyzer().stemmer.language(), Some(Algorithm::French));
+
+        let query = db.parse_query("mange", (), true).unwrap();
+        let docs: Vec<u32> = db.query(&query).map(|result| result.id.doc).collect();
+        assert_eq!(docs, [1, 2]);
+    }
+}
@@ -0,0 +1,92 @@
+use std::borrow::Cow;
+
+use storage::{CompressedMap, MultiMap, PostingIter, PostingList};
+
+use crate::sentence::SentenceId;
+
+// the term -> sentence index, stored either as plain arrays or compressed
+pub(crate) enum PostingIndex {
+    Raw(MultiMap<u32, SentenceId>),
+    Compressed(CompressedMap<u32, SentenceId>),
+}
+
+impl PostingIndex {
+    #[inline(always)]
+    pub fn get(&self, term: &u32) -> Option<Postings<'_>> {
+        match self {
+            PostingIndex::Raw(map) => map.get(term).map(Postings::Raw),
+            PostingIndex::Compressed(map) => map.get(term).map(Postings::Compressed),
+        }
+    }
+}
+
+// a sorted posting list, in either format
+#[derive(Clone, Copy)]
+pub(crate) enum Postings<'a> {
+    Raw(&'a [SentenceId]),
+    Compressed(PostingList<'a, SentenceId>),
+}
+
+impl<'a> Postings<'a> {
+    pub fn len(&self) -> usize {
+        match self {
+            Postings::Raw(ids) => ids.len(),
+            Postings::Compressed(list) => list.len(),
+        }
+    }
+
+    // the whole list. free for raw postings
+    pub fn decode(&self) -> Cow<'a, [SentenceId]> {
+        match self {
+            Postings::Raw(ids) => Cow::Borrowed(ids),
+            Postings::Compressed(list) => Cow::Owned(list.decode()),
+        }
+    }
+
+    pub fn iter(&self) -> PostingsIter<'a> {
+        match self {
+            Postings::Raw(ids) => PostingsIter::Raw(ids.iter()),
+            Postings::Compressed(list) => PostingsIter::Compressed(list.iter()),
+        }
+    }
+
+    // the postings belonging to one document
+    pub fn document(&self, doc: u32) -> Cow<'a, [SentenceId]> {
+        match self {
+            Postings::Raw(ids) => {
+                let start = ids.partition_point(|id| id.doc < doc);
+                let end = start + ids[start..].partition_point(|id| id.doc <= doc);
+                Cow::Borrowed(&ids[start..end])
+            }
+            Postings::Compressed(list) => {
+                let mut cursor = list.cursor();
+                let mut ids = Vec::new();
+                let mut next = cursor.seek(SentenceId::new(doc, 0));
+
+                while let Some(id) = next.filter(|id| id.doc == doc) {
+                    ids.push(id);
+                    next = cursor.advance();
+                }
+
+                Cow::Owned(ids)
+            }
+        }
+    }
+}
+
+pub(crate) enum PostingsIter<'a> {
+    Raw(std::slice::Iter<'a, SentenceId>),
+    Compressed(PostingIter<'a, SentenceId>),
+}
+
+impl Iterator for PostingsIter<'_> {
+    type Item = SentenceId;
+
+    #[inline(always)]
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            PostingsIter::Raw(ids) => ids.next().copied(),
+            PostingsIter::Compressed(ids) => ids.next(),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::marker::PhantomData;
+
+use rayon::slice::ParallelSliceMut;
+
+use crate::{
+    id_list::SentenceIdList,
+    searcher::{SearchEngine, SearchResult},
+    DocumentMetadata, SentenceMetadata,
+};
+
+use super::{CallerType, DocumentFilter, DynamicQuery, Query};
+
+// matches sentences matched by `include`, but not by `exclude`
+pub struct DifferenceQuery<D, S, DF>
+where
+    D: DocumentMetadata,
+    S: SentenceMetadata,
+    DF: DocumentFilter<D>,
+{
+    include: Box<DynamicQuery<D, S, DF>>,
+    exclude: Box<DynamicQuery<D, S, DF>>,
+    spooky: PhantomData<(D, S)>,
+}
+
+impl<D, S, DF> DifferenceQuery<D, S, DF>
+where
+    D: DocumentMetadata,
+    S: SentenceMetadata,
+    DF: DocumentFilter<D>,
+{
+    pub fn new(
+        include: impl Into<DynamicQuery<D, S, DF>>,
+        exclude: impl Into<DynamicQuery<D, S, DF>>,
+    ) -> DifferenceQuery<D, S, DF> {
+        DifferenceQuery {
+            include: Box::new(include.into()),
+            exclude: Box::new(exclude.into()),
+            spooky: PhantomData,
+        }
+    }
+}
+
+impl<D, S, DF> Query<D, S> for DifferenceQuery<D, S, DF>
+where
+    D: DocumentMetadata,
+    S: SentenceMetadata,
+    DF: DocumentFilter<D>,
+{
+    fn find_sentence_ids(&self, db: &SearchEngine<D, S>, caller: CallerType) -> SentenceIdList {
+        // the excluded set needs no deduping or document filtering, like part of an intersection
+        let (mut ids, mut excluded) = rayon::join(
+            || self.include.find_sentence_ids(db, caller),
+            || self.exclude.find_sentence_ids(db, CallerType::Intersection),
+        );
+
+        ids.ids.par_sort_unstable();
+        excluded.ids.par_sort_unstable();
+
+        if !self.exclude.exact_ids() {
+            // excluded candidates might not really match. only the ones that could be subtracted
+            // are worth checking, and filter_map can only check its own candidates
+            excluded.ids.dedup();
+            excluded.intersect(&ids.ids);
+            excluded.ids.retain(|id| {
+                let mut result = SearchResult {
+                    id: *id,
+                    highlighted_parts: Vec::new(),
+                    sentence: db.sentences.get(id).unwrap(),
+                    score: 0.0,
+                };
+
+                self.exclude.filter_map(&mut result)
+            });
+        }
+
+        ids.subtract(&excluded.ids);
+
+        ids
+    }
+
+    fn exact_ids(&self) -> bool {
+        // exclusions are all resolved up front
+        self.include.exact_ids()
+    }
+
+    fn filter_map(&self, result: &mut SearchResult<'_, S>) -> bool {
+        self.include.filter_map(result)
+    }
+
+    fn find_highlights(&self, result: &mut SearchResult<'_, S>) {
+        // excluded terms never show up in a match, so only the included side gets highlighted
+        self.include.find_highlights(result);
+    }
+
+    fn collect_terms(&self, terms: &mut Vec<u32>) {
+        self.include.collect_terms(terms);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::database;
+
+    #[test]
+    fn test_exclusions() {
+        let docs = [
+            "The wizard has a red scale.",
+            "The wizard saw a dragon with a red scale.",
+            "The wizard rides a drake.",
+            "The dragon has a red scale.",
+        ];
+        let db = database("difference_exclusions", &docs, |_| {});
+
+        let matching = |query: &str| -> Vec<u32> {
+            let query = db.parse_query(query, (), true).unwrap();
+            db.query(&query).map(|result| result.id.doc).collect()
+        };
+
+        assert_eq!(matching("wizard -dragon"), [1, 3]);
+        assert_eq!(matching("wizard -\"red scale\""), [3]);
+        // the excluded side only has candidates, which have to be checked before they're dropped
+        assert_eq!(
+            matching("wizard -((dragon OR drake) AND \"red scale\")"),
+            [1, 3]
+        );
+        assert_eq!(matching("wizard -(drake OR \"dragon with\")"), [1]);
+    }
+}
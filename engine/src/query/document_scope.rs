@@ -0,0 +1,213 @@
+use std::marker::PhantomData;
+
+use rayon::{
+    prelude::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator},
+    slice::ParallelSliceMut,
+};
+use smallvec::SmallVec;
+use storage::Storage;
+
+use crate::{
+    highlight::collapse_overlapped_ranges,
+    id_list::{gallop, SentenceIdList},
+    searcher::{SearchEngine, SearchResult},
+    sentence::SentenceId,
+    DocumentMetadata, SentenceMetadata,
+};
+
+use super::{CallerType, DocumentFilter, DynamicQuery, Query};
+
+// matches documents where every query matches at least one sentence, yielding the sentences that
+// matched any of them
+#[derive(Default)]
+pub struct DocumentScopeQuery<D, S, DF>
+where
+    D: DocumentMetadata,
+    S: SentenceMetadata,
+    DF: DocumentFilter<D>,
+{
+    queries: SmallVec<[Box<DynamicQuery<D, S, DF>>; 4]>,
+    document_filter: DF,
+    spooky: PhantomData<(D, S)>,
+}
+
+impl<D, S, DF> DocumentScopeQuery<D, S, DF>
+where
+    D: DocumentMetadata,
+    S: SentenceMetadata,
+    DF: DocumentFilter<D>,
+{
+    pub fn and(&mut self, query: impl Into<DynamicQuery<D, S, DF>>) {
+        self.queries.push(Box::new(query.into()))
+    }
+
+    pub fn from_dynamic(
+        queries: impl IntoIterator<Item = impl Into<DynamicQuery<D, S, DF>>>,
+        document_filter: DF,
+    ) -> DocumentScopeQuery<D, S, DF> {
+        DocumentScopeQuery {
+            queries: SmallVec::from_iter(queries.into_iter().map(|v| v.into()).map(Box::new)),
+            document_filter,
+            spooky: PhantomData,
+        }
+    }
+}
+
+impl<D, S, DF> Query<D, S> for DocumentScopeQuery<D, S, DF>
+where
+    D: DocumentMetadata,
+    S: SentenceMetadata,
+    DF: DocumentFilter<D>,
+{
+    fn find_sentence_ids(&self, db: &SearchEngine<D, S>, _caller: CallerType) -> SentenceIdList {
+        // every query's actual matches, sorted. candidates that might not match get checked
+        // here, since a false one could otherwise let its whole document in
+        let sets: Vec<Vec<SentenceId>> = self
+            .queries
+            .par_iter()
+            .map(|query| {
+                let mut ids: Vec<SentenceId> = query
+                    .find_sentence_ids(db, CallerType::Intersection)
+                    .ids
+                    .into_par_iter()
+                    .filter(SentenceId::is_valid)
+                    .collect();
+
+                ids.par_sort_unstable();
+                ids.dedup();
+
+                if !query.exact_ids() {
+                    ids.retain(|id| {
+                        let mut result = SearchResult {
+                            id: *id,
+                            highlighted_parts: Vec::new(),
+                            sentence: db.sentences.get(id).unwrap(),
+                            score: 0.0,
+                        };
+
+                        query.filter_map(&mut result)
+                    });
+                }
+
+                ids
+            })
+            .collect();
+
+        let Some((first, rest)) = sets.split_first() else {
+            return SentenceIdList { ids: Vec::new() };
+        };
+
+        let mut docs: Vec<u32> = first.iter().map(|id| id.doc).collect();
+        docs.dedup();
+
+        for set in rest {
+            if docs.is_empty() {
+                break;
+            }
+
+            // both are sorted by document, so each lookup starts where the last one ended
+            let mut set = &set[..];
+            docs.retain(|doc| {
+                set = &set[gallop(set, |id| id.doc < *doc)..];
+                set.first().is_some_and(|id| id.doc == *doc)
+            });
+        }
+
+        if DF::needed() {
+            docs.retain(|doc| {
+                self.document_filter
+                    .filter_document_id(*doc, unsafe { db.doc_meta.get_unchecked(*doc as usize) })
+            });
+        }
+
+        let mut ids: Vec<SentenceId> = sets
+            .into_iter()
+            .flatten()
+            .filter(|id| docs.binary_search(&id.doc).is_ok())
+            .collect();
+
+        ids.par_sort_unstable();
+        ids.dedup();
+
+        SentenceIdList { ids }
+    }
+
+    fn exact_ids(&self) -> bool {
+        true
+    }
+
+    fn filter_map(&self, result: &mut SearchResult<'_, S>) -> bool {
+        let mut highlights = Vec::new();
+        let mut matched = false;
+
+        for query in &self.queries {
+            matched |= query.filter_map(result);
+
+            highlights.append(&mut result.highlighted_parts);
+        }
+
+        highlights.par_sort_by_key(|v| v.start);
+        result.highlighted_parts = collapse_overlapped_ranges(&highlights);
+
+        matched
+    }
+
+    fn find_highlights(&self, result: &mut SearchResult<'_, S>) {
+        let mut highlights = Vec::new();
+        for query in &self.queries {
+            query.find_highlights(result);
+
+            highlights.append(&mut result.highlighted_parts);
+        }
+
+        highlights.par_sort_by_key(|v| v.start);
+        result.highlighted_parts = collapse_overlapped_ranges(&highlights);
+    }
+
+    fn collect_terms(&self, terms: &mut Vec<u32>) {
+        for query in &self.queries {
+            query.collect_terms(terms);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{database, render};
+
+    #[test]
+    fn test_document_scope() {
+        let docs = [
+            "The wizard climbs.\nA tall tower.\nNothing here.",
+            "The wizard sleeps.",
+            "A tower.",
+            "The wizard in the tower.",
+            "Dragon, red.\nA tower.",
+        ];
+        let db = database("document_scope", &docs, |_| {});
+
+        // each matching sentence, with its highlights
+        let matching = |query: &str| -> Vec<(u32, String)> {
+            let query = db.parse_query(query, (), true).unwrap();
+            db.query(&query)
+                .map(|result| (result.id.doc, render(&result.highlights())))
+                .collect()
+        };
+
+        assert_eq!(
+            matching("doc:(wizard AND tower)"),
+            [
+                (1, "The [wizard|0] climbs.".to_string()),
+                (1, "A tall [tower|1].".to_string()),
+                (4, "The [wizard|0] in the [tower|1].".to_string()),
+            ]
+        );
+        assert_eq!(
+            matching("wizard AND tower"),
+            [(4, "The [wizard|0] in the [tower|1].".to_string())]
+        );
+
+        // a sentence with every word of a phrase, but not the phrase, doesn't count
+        assert_eq!(matching("doc:(\"red dragon\" AND tower)"), []);
+    }
+}
@@ -0,0 +1,66 @@
+use crate::{
+    id_list::SentenceIdList,
+    searcher::{SearchEngine, SearchResult},
+    DocumentMetadata, SentenceMetadata,
+};
+
+use super::{CallerType, DocumentFilter, KeywordsQuery, Query};
+
+// matches sentences containing any of the terms a prefix, wildcard pattern or fuzzy term
+// expanded to, all highlighted with the same class. highlights land on whichever variant matched
+#[derive(Clone)]
+pub struct ExpansionQuery<D, S, DF>
+where
+    D: DocumentMetadata,
+    S: SentenceMetadata,
+    DF: DocumentFilter<D>,
+{
+    pub(crate) keywords: KeywordsQuery<D, S, DF>,
+    pub(crate) truncated: bool,
+}
+
+impl<D, S, DF> ExpansionQuery<D, S, DF>
+where
+    D: DocumentMetadata,
+    S: SentenceMetadata,
+    DF: DocumentFilter<D>,
+{
+    // whether the pattern matched more terms than the expansion limit allowed
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn terms(&self) -> &[u32] {
+        &self.keywords.keywords
+    }
+}
+
+impl<D, S, DF> Query<D, S> for ExpansionQuery<D, S, DF>
+where
+    D: DocumentMetadata,
+    S: SentenceMetadata,
+    DF: DocumentFilter<D>,
+{
+    #[inline(always)]
+    fn find_sentence_ids(&self, db: &SearchEngine<D, S>, caller: CallerType) -> SentenceIdList {
+        self.keywords.find_sentence_ids(db, caller)
+    }
+
+    fn exact_ids(&self) -> bool {
+        true
+    }
+
+    #[inline(always)]
+    fn filter_map(&self, result: &mut SearchResult<'_, S>) -> bool {
+        self.keywords.filter_map(result)
+    }
+
+    #[inline(always)]
+    fn find_highlights(&self, result: &mut SearchResult<'_, S>) {
+        self.keywords.find_highlights(result)
+    }
+
+    fn collect_terms(&self, terms: &mut Vec<u32>) {
+        self.keywords.collect_terms(terms)
+    }
+}
@@ -1,11 +1,25 @@
-use crate::{sentence::ArchivedSentence, DocumentMetadata, SentenceMetadata};
+use std::{collections::HashSet, ops::RangeBounds, sync::Arc};
+
+use roaring::RoaringBitmap;
+use storage::Storage;
+
+use crate::{
+    searcher::SearchEngine, sentence::ArchivedSentence, DocumentMetadata, SentenceMetadata,
+};
 
 pub trait SentenceFilter<S: SentenceMetadata>: Send + Sync {
     fn filter_sentence(&self, sentence: &ArchivedSentence<S>) -> bool;
 }
 
 pub trait DocumentFilter<D: DocumentMetadata>: Send + Sync {
-    fn filter_document(&self, document_meta: &D) -> bool;
+    // filters can go by the document's id, its metadata, or both
+    fn filter_document_id(&self, id: u32, document_meta: &D) -> bool;
+
+    // every document that passes, if the filter knows them up front. ids are then checked against
+    // the bitmap without reading any document metadata
+    fn document_bitmap(&self) -> Option<&RoaringBitmap> {
+        None
+    }
 
     fn needed() -> bool {
         true
@@ -17,14 +31,14 @@ where
     T: Fn(&D) -> bool + Send + Sync,
 {
     #[inline(always)]
-    fn filter_document(&self, document_meta: &D) -> bool {
+    fn filter_document_id(&self, _id: u32, document_meta: &D) -> bool {
         self(document_meta)
     }
 }
 
 impl<D: DocumentMetadata> DocumentFilter<D> for () {
     #[inline(always)]
-    fn filter_document(&self, _document_meta: &D) -> bool {
+    fn filter_document_id(&self, _id: u32, _document_meta: &D) -> bool {
         true
     }
 
@@ -33,6 +47,170 @@ impl<D: DocumentMetadata> DocumentFilter<D> for () {
     }
 }
 
+// passes documents that pass both filters
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct And<A, B>(pub A, pub B);
+
+// passes documents that pass either filter
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Or<A, B>(pub A, pub B);
+
+// passes documents the filter rejects
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Not<A>(pub A);
+
+// passes the documents with these ids
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InSet(pub HashSet<u32>);
+
+// passes documents where a field of their metadata, picked out by `projection`, falls in `range`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InRange<P, R> {
+    pub projection: P,
+    pub range: R,
+}
+
+impl<P, R> InRange<P, R> {
+    pub fn new(projection: P, range: R) -> InRange<P, R> {
+        InRange { projection, range }
+    }
+}
+
+impl<D, A, B> DocumentFilter<D> for And<A, B>
+where
+    D: DocumentMetadata,
+    A: DocumentFilter<D>,
+    B: DocumentFilter<D>,
+{
+    #[inline(always)]
+    fn filter_document_id(&self, id: u32, document_meta: &D) -> bool {
+        self.0.filter_document_id(id, document_meta) && self.1.filter_document_id(id, document_meta)
+    }
+
+    // only when the other side lets everything through
+    fn document_bitmap(&self) -> Option<&RoaringBitmap> {
+        match (A::needed(), B::needed()) {
+            (true, false) => self.0.document_bitmap(),
+            (false, true) => self.1.document_bitmap(),
+            _ => None,
+        }
+    }
+
+    fn needed() -> bool {
+        A::needed() || B::needed()
+    }
+}
+
+impl<D, A, B> DocumentFilter<D> for Or<A, B>
+where
+    D: DocumentMetadata,
+    A: DocumentFilter<D>,
+    B: DocumentFilter<D>,
+{
+    #[inline(always)]
+    fn filter_document_id(&self, id: u32, document_meta: &D) -> bool {
+        self.0.filter_document_id(id, document_meta) || self.1.filter_document_id(id, document_meta)
+    }
+
+    // if either side lets everything through, so does this
+    fn needed() -> bool {
+        A::needed() && B::needed()
+    }
+}
+
+impl<D, A> DocumentFilter<D> for Not<A>
+where
+    D: DocumentMetadata,
+    A: DocumentFilter<D>,
+{
+    #[inline(always)]
+    fn filter_document_id(&self, id: u32, document_meta: &D) -> bool {
+        !self.0.filter_document_id(id, document_meta)
+    }
+}
+
+impl<D: DocumentMetadata> DocumentFilter<D> for InSet {
+    #[inline(always)]
+    fn filter_document_id(&self, id: u32, _document_meta: &D) -> bool {
+        self.0.contains(&id)
+    }
+}
+
+impl<D, T, P, R> DocumentFilter<D> for InRange<P, R>
+where
+    D: DocumentMetadata,
+    T: PartialOrd,
+    P: Fn(&D) -> T + Send + Sync,
+    R: RangeBounds<T> + Send + Sync,
+{
+    #[inline(always)]
+    fn filter_document_id(&self, _id: u32, document_meta: &D) -> bool {
+        self.range.contains(&(self.projection)(document_meta))
+    }
+}
+
+// a precomputed set of documents, from a metadata predicate or a list of ids. it's cheap to
+// clone, so it can be built once and reused as the filter of any number of queries
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DocumentBitmap {
+    docs: Arc<RoaringBitmap>,
+}
+
+impl DocumentBitmap {
+    pub fn from_ids(ids: impl IntoIterator<Item = u32>) -> DocumentBitmap {
+        DocumentBitmap::from(ids.into_iter().collect::<RoaringBitmap>())
+    }
+
+    // every document whose metadata matches
+    pub fn from_predicate<D: DocumentMetadata, S: SentenceMetadata>(
+        db: &SearchEngine<D, S>,
+        predicate: impl Fn(&D) -> bool,
+    ) -> DocumentBitmap {
+        DocumentBitmap::from_ids(
+            (0..)
+                .map_while(|id| Some((id, db.doc_meta.try_get(id as usize)?)))
+                .filter(|(_, meta)| predicate(meta))
+                .map(|(id, _)| id),
+        )
+    }
+
+    pub fn contains(&self, id: u32) -> bool {
+        self.docs.contains(id)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.docs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    // for combining bitmaps with roaring's set operations
+    pub fn bitmap(&self) -> &RoaringBitmap {
+        &self.docs
+    }
+}
+
+impl From<RoaringBitmap> for DocumentBitmap {
+    fn from(docs: RoaringBitmap) -> Self {
+        DocumentBitmap {
+            docs: Arc::new(docs),
+        }
+    }
+}
+
+impl<D: DocumentMetadata> DocumentFilter<D> for DocumentBitmap {
+    #[inline(always)]
+    fn filter_document_id(&self, id: u32, _document_meta: &D) -> bool {
+        self.docs.contains(id)
+    }
+
+    fn document_bitmap(&self) -> Option<&RoaringBitmap> {
+        Some(&self.docs)
+    }
+}
+
 impl<T, S: SentenceMetadata> SentenceFilter<S> for T
 where
     T: Fn(&ArchivedSentence<S>) -> bool + Send + Sync,
@@ -42,3 +220,97 @@ where
         self(sentence)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        query::{And, DocumentBitmap, DocumentFilter, InRange, InSet, Not, Or},
+        test::database,
+        Database,
+    };
+
+    // the documents with a sentence about dragons that pass `filter`
+    fn passing<F: DocumentFilter<u32> + Clone + 'static>(
+        db: &Database<String, u32, ()>,
+        filter: F,
+    ) -> Vec<u32> {
+        let query = db.parse_query("dragon", filter, true).unwrap();
+        db.query(&query).map(|result| result.id.doc).collect()
+    }
+
+    #[test]
+    fn test_combinators() {
+        let docs = [
+            "A dragon.",
+            "Two dragons.",
+            "Three dragons.",
+            "Four dragons.",
+        ];
+        let db = database("filter_combinators", &docs, |_| {});
+        let bitmap = |ids: &[u32]| DocumentBitmap::from_ids(ids.iter().copied());
+
+        assert_eq!(passing(&db, ()), [1, 2, 3, 4]);
+
+        // a side that lets everything through makes the whole Or a no-op
+        assert!(!<Or<(), DocumentBitmap> as DocumentFilter<u32>>::needed());
+        assert_eq!(passing(&db, Or((), bitmap(&[2]))), [1, 2, 3, 4]);
+        assert_eq!(passing(&db, Or(InSet([1].into()), bitmap(&[3]))), [1, 3]);
+
+        assert_eq!(passing(&db, Not(())), Vec::<u32>::new());
+        assert_eq!(passing(&db, Not(InSet([1, 2].into()))), [3, 4]);
+        assert_eq!(
+            passing(&db, Or(InSet([1].into()), |meta: &u32| *meta == 30)),
+            [1, 3]
+        );
+
+        assert_eq!(
+            passing(&db, And(bitmap(&[1, 2, 3]), bitmap(&[2, 3, 4]))),
+            [2, 3]
+        );
+        assert_eq!(passing(&db, And((), bitmap(&[4]))), [4]);
+    }
+
+    #[test]
+    fn test_id_filters_wrapped() {
+        // id based filters only answer for a given id, also when they're wrapped
+        let not = Not(InSet([1].into()));
+        assert!(!not.filter_document_id(1, &10u32));
+        assert!(not.filter_document_id(2, &20u32));
+
+        let or = Or(InSet([1].into()), |meta: &u32| *meta == 30);
+        assert!(or.filter_document_id(1, &10));
+        assert!(!or.filter_document_id(2, &20));
+        assert!(or.filter_document_id(3, &30));
+
+        let not_bitmap = Not(DocumentBitmap::from_ids([2]));
+        assert!(<_ as DocumentFilter<u32>>::filter_document_id(
+            &not_bitmap,
+            1,
+            &10
+        ));
+        assert!(!<_ as DocumentFilter<u32>>::filter_document_id(
+            &not_bitmap,
+            2,
+            &20
+        ));
+    }
+
+    #[test]
+    fn test_in_range_bounds() {
+        let docs = [
+            "A dragon.",
+            "Two dragons.",
+            "Three dragons.",
+            "Four dragons.",
+        ];
+        let db = database("filter_in_range", &docs, |_| {});
+        let meta = |meta: &u32| *meta;
+
+        assert_eq!(passing(&db, InRange::new(meta, 20..=30)), [2, 3]);
+        assert_eq!(passing(&db, InRange::new(meta, 20..30)), [2]);
+        assert_eq!(passing(&db, InRange::new(meta, 10..=40)), [1, 2, 3, 4]);
+        assert_eq!(passing(&db, InRange::new(meta, ..10)), Vec::<u32>::new());
+        assert_eq!(passing(&db, InRange::new(meta, 40..)), [4]);
+        assert_eq!(passing(&db, InRange::new(meta, 41..)), Vec::<u32>::new());
+    }
+}
@@ -4,17 +4,19 @@ use rayon::{
 };
 use smallvec::SmallVec;
 use std::marker::PhantomData;
-use storage::Storage;
 
 use crate::{
     highlight::collapse_overlapped_ranges,
     id_list::SentenceIdList,
+    postings::Postings,
     searcher::{SearchEngine, SearchResult},
-    sentence::SentenceId,
     DocumentMetadata, SentenceMetadata,
 };
 
-use super::{CallerType, DocumentFilter, DynamicQuery, PhraseQuery, Query};
+use super::{
+    phrase::{phrase_term_set, scan_candidates},
+    CallerType, DocumentFilter, DynamicQuery, PhraseQuery, Query,
+};
 
 #[derive(Default)]
 pub struct IntersectingQuery<D, S, DF>
@@ -70,19 +72,27 @@ where
 
             res.ids.par_sort_unstable();
             for set in rhs {
+                if res.is_empty() {
+                    break;
+                }
+
                 set.ids.par_sort_unstable();
-                res.retain(|v| {
-                    set.ids.binary_search(v).is_ok()
-                        && self
-                            .document_filter
-                            .filter_document(unsafe { db.doc_meta.get_unchecked(v.doc as usize) })
-                })
+                res.intersect(&set.ids);
             }
+
+            // keyword queries leave duplicates in when they're part of an intersection
+            res.ids.dedup();
+
+            res.filter_documents(&self.document_filter, db);
         }
 
         sets.swap_remove(0)
     }
 
+    fn exact_ids(&self) -> bool {
+        self.queries.iter().all(|query| query.exact_ids())
+    }
+
     fn filter_map(&self, result: &mut SearchResult<'_, S>) -> bool {
         let mut highlights = Vec::new();
         for query in &self.queries {
@@ -114,6 +124,12 @@ where
         highlights.par_sort_by_key(|v| v.start);
         result.highlighted_parts = collapse_overlapped_ranges(&highlights);
     }
+
+    fn collect_terms(&self, terms: &mut Vec<u32>) {
+        for query in &self.queries {
+            query.collect_terms(terms);
+        }
+    }
 }
 
 #[derive(Default)]
@@ -153,48 +169,48 @@ where
     DF: DocumentFilter<D>,
 {
     fn find_sentence_ids(&self, db: &SearchEngine<D, S>, _caller: CallerType) -> SentenceIdList {
-        let mut term_sets: Vec<&[SentenceId]> = self
+        let mut term_sets: Vec<Postings> = self
             .queries
             .par_iter()
             .flat_map(|query| {
                 query
                     .phrase
                     .par_iter()
-                    .map(|term| db.index.get(term).unwrap_or(&[]))
+                    .filter_map(|term| phrase_term_set(db, *term))
             })
             .collect();
 
+        if term_sets.is_empty() {
+            let terms: Vec<u32> = self
+                .queries
+                .iter()
+                .flat_map(|query| query.phrase.iter().copied())
+                .collect();
+
+            return scan_candidates(db, &terms, &self.document_filter);
+        }
+
         term_sets.sort_by_key(|v| v.len());
 
-        let mut sentence_ids = SentenceIdList::from_slice(term_sets[0]);
+        let mut sentence_ids = SentenceIdList::from_slice(&term_sets[0].decode());
 
-        match DF::needed() {
-            true if term_sets.len() > 1 => {
-                for set in &term_sets[1..] {
-                    sentence_ids.retain(|v| {
-                        self.document_filter
-                            .filter_document(unsafe { db.doc_meta.get_unchecked(v.doc as usize) })
-                            && set.binary_search(v).is_ok()
-                    });
-                }
-            }
-            false if term_sets.len() > 1 => {
-                for set in &term_sets[1..] {
-                    sentence_ids.retain(|v| set.binary_search(v).is_ok());
-                }
+        for set in &term_sets[1..] {
+            if sentence_ids.is_empty() {
+                break;
             }
-            true => {
-                sentence_ids.retain(|id| {
-                    self.document_filter
-                        .filter_document(unsafe { db.doc_meta.get_unchecked(id.doc as usize) })
-                });
-            }
-            false => {}
+
+            sentence_ids.intersect_postings(*set);
         }
 
+        sentence_ids.filter_documents(&self.document_filter, db);
+
         sentence_ids
     }
 
+    fn exact_ids(&self) -> bool {
+        self.queries.iter().all(|query| query.exact_ids())
+    }
+
     fn filter_map(&self, result: &mut SearchResult<'_, S>) -> bool {
         let mut highlights = Vec::new();
         for query in &self.queries {
@@ -226,4 +242,10 @@ where
         highlights.par_sort_by_key(|v| v.start);
         result.highlighted_parts = collapse_overlapped_ranges(&highlights);
     }
+
+    fn collect_terms(&self, terms: &mut Vec<u32>) {
+        for query in &self.queries {
+            query.collect_terms(terms);
+        }
+    }
 }
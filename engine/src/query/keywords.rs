@@ -2,10 +2,9 @@ use std::marker::PhantomData;
 
 use rayon::slice::ParallelSliceMut;
 use rkyv::Archive;
-use storage::Storage;
 
 use crate::{
-    highlight::Highlighter,
+    highlight::{collapse_overlapped_ranges, Highlighter},
     id_list::SentenceIdList,
     searcher::{SearchEngine, SearchResult},
     sentence::{ArchivedSentence, SentenceId, SentenceRange},
@@ -36,11 +35,11 @@ where
     fn find_sentence_ids(&self, db: &SearchEngine<D, S>, caller: CallerType) -> SentenceIdList {
         let mut ids = if let [lhs, rhs] = &self.keywords[..] {
             let (lhs, rhs) = (
-                db.index.get(lhs).unwrap_or(&[]),
-                db.index.get(rhs).unwrap_or(&[]),
+                db.index.get(lhs).map(|v| v.decode()).unwrap_or_default(),
+                db.index.get(rhs).map(|v| v.decode()).unwrap_or_default(),
             );
 
-            let mut ids = SentenceIdList::merge_slices(lhs, rhs);
+            let mut ids = SentenceIdList::merge_slices(&lhs, &rhs);
             if !caller.intersect() {
                 ids.ids.dedup();
             }
@@ -49,7 +48,7 @@ where
         } else {
             let mut ids: Vec<SentenceId> = Vec::new();
             for term in &self.keywords {
-                let set = db.index.get(term).unwrap_or(&[]);
+                let set = db.index.get(term).map(|v| v.decode()).unwrap_or_default();
 
                 let dst_len = ids.len();
                 let src_len = set.len();
@@ -70,31 +69,55 @@ where
         //     ids
         // };
 
-        if DF::needed() && !caller.intersect() {
-            ids.retain(|id| {
-                self.document_filter
-                    .filter_document(unsafe { db.doc_meta.get_unchecked(id.doc as usize) })
-            });
+        if !caller.intersect() {
+            ids.filter_documents(&self.document_filter, db);
         }
 
         ids
     }
 
+    fn exact_ids(&self) -> bool {
+        true
+    }
+
+    fn filter_map(&self, result: &mut SearchResult<'_, S>) -> bool {
+        // every id is a match, this is only here to highlight it
+        result.highlighted_parts = self.highlighter.highlight(result.sentence);
+        true
+    }
+
     #[inline(always)]
     fn find_highlights(&self, result: &mut SearchResult<'_, S>) {
         result.highlighted_parts = self.highlighter.highlight(result.sentence);
     }
+
+    fn collect_terms(&self, terms: &mut Vec<u32>) {
+        terms.extend(self.keywords.iter().filter(|term| **term != 0));
+    }
 }
 
 #[derive(Clone)]
 pub struct KeywordHighlighter {
     keywords: Vec<u32>,
+    // each keyword gets its own class, counting up from this one, unless the class is shared
+    first_class: u32,
+    shared_class: bool,
 }
 
 impl KeywordHighlighter {
-    pub fn new(keywords: &[u32]) -> KeywordHighlighter {
+    pub fn new(keywords: &[u32], first_class: u32) -> KeywordHighlighter {
         KeywordHighlighter {
             keywords: keywords.into(),
+            first_class,
+            shared_class: false,
+        }
+    }
+
+    // highlights every keyword with the same class
+    pub fn with_shared_class(keywords: &[u32], class: u32) -> KeywordHighlighter {
+        KeywordHighlighter {
+            shared_class: true,
+            ..KeywordHighlighter::new(keywords, class)
         }
     }
 }
@@ -106,9 +129,14 @@ impl<'a> Highlighter<'a> for KeywordHighlighter {
     ) -> Vec<SentenceRange> {
         let mut ranges: Vec<SentenceRange> = Vec::with_capacity(64);
 
-        for keyword in &self.keywords {
+        for (idx, keyword) in self.keywords.iter().enumerate() {
+            let class = match self.shared_class {
+                true => self.first_class,
+                false => self.first_class + idx as u32,
+            };
+
             let Some(tokens) = sentence.terms_by_value.get(keyword) else {
-                continue
+                continue;
             };
 
             for token_idx in tokens.iter() {
@@ -117,12 +145,35 @@ impl<'a> Highlighter<'a> for KeywordHighlighter {
                 ranges.push(SentenceRange {
                     start: token.start as usize,
                     end: token.end as usize,
+                    class,
                 });
             }
         }
 
         ranges.sort_unstable_by_key(|t| t.start);
 
-        ranges
+        // keywords can share tokens, e.g. when two of them stem to the same term
+        collapse_overlapped_ranges(&ranges)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{database, render};
+
+    #[test]
+    fn test_shared_tokens_highlighted_once() {
+        let db = database("keywords_shared", &["The wizard runs home."], |_| {});
+
+        for query in ["run OR running", "runs OR runs"] {
+            let query = db.parse_query(query, (), true).unwrap();
+            let results: Vec<_> = db.query(&query).collect();
+
+            assert_eq!(results.len(), 1);
+            assert_eq!(
+                render(&results[0].highlights()),
+                "The wizard [runs|0] home."
+            );
+        }
     }
 }
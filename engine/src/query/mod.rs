@@ -2,17 +2,25 @@ use std::marker::PhantomData;
 
 use enum_dispatch::enum_dispatch;
 
+mod difference;
+mod document_scope;
+mod expansion;
 mod filter;
 mod intersect;
 mod keywords;
 mod phrase;
+mod proximity;
 mod union_query;
 
+pub use difference::*;
+pub use document_scope::*;
+pub use expansion::*;
 pub use filter::*;
 
 pub use intersect::*;
 pub use keywords::*;
 pub use phrase::*;
+pub use proximity::*;
 pub use union_query::*;
 pub mod parser;
 
@@ -27,11 +35,19 @@ pub trait Query<D: DocumentMetadata, S: SentenceMetadata> {
     // is_part_of_intersect can be used to, for example, ignore dedup() in keyword queries
     fn find_sentence_ids(&self, db: &SearchEngine<D, S>, caller: CallerType) -> SentenceIdList;
 
+    // whether every id from find_sentence_ids is a real match, i.e. filter_map never rejects one
+    fn exact_ids(&self) -> bool {
+        false
+    }
+
     fn filter_map(&self, _result: &mut SearchResult<'_, S>) -> bool {
         true
     }
 
     fn find_highlights(&self, sentence: &mut SearchResult<'_, S>);
+
+    // the terms a matching sentence should be scored by
+    fn collect_terms(&self, _terms: &mut Vec<u32>) {}
 }
 
 #[enum_dispatch(Query<D,S>)]
@@ -41,6 +57,10 @@ pub enum DynamicQuery<D: DocumentMetadata, S: SentenceMetadata, DF: DocumentFilt
     Intersection(IntersectingQuery<D, S, DF>),
     PhraseIntersection(IntersectingPhraseQuery<D, S, DF>),
     Union(UnionQuery<D, S, DF>),
+    Difference(DifferenceQuery<D, S, DF>),
+    Proximity(ProximityQuery<D, S, DF>),
+    DocumentScope(DocumentScopeQuery<D, S, DF>),
+    Expansion(ExpansionQuery<D, S, DF>),
 }
 
 #[derive(Clone, Copy)]
@@ -51,6 +71,8 @@ where
     DF: DocumentFilter<D>,
 {
     terms: &'a [u32],
+    exact: bool,
+    class: u32,
     document_filter: DF,
     spooky: PhantomData<(D, S)>,
 }
@@ -59,6 +81,8 @@ impl<'a, D: DocumentMetadata, S: SentenceMetadata> QueryBuilder<'a, D, S, ()> {
     pub fn start(phrase: &'a [u32]) -> QueryBuilder<'a, D, S, ()> {
         QueryBuilder {
             terms: phrase,
+            exact: false,
+            class: 0,
             document_filter: (),
             spooky: PhantomData,
         }
@@ -77,15 +101,40 @@ where
     ) -> QueryBuilder<'a, D, S, NDF> {
         QueryBuilder {
             terms: self.terms,
+            exact: self.exact,
+            class: self.class,
             document_filter: doc_filter,
             spooky: PhantomData,
         }
     }
 
+    // match surface forms only. the terms must come from FrozenTermMap::tokenize_exact_phrase
+    pub fn exact(self) -> QueryBuilder<'a, D, S, DF> {
+        QueryBuilder {
+            exact: true,
+            ..self
+        }
+    }
+
+    // highlight class for the query's matches. keywords get one class each, counting up from it
+    pub fn class(self, class: u32) -> QueryBuilder<'a, D, S, DF> {
+        QueryBuilder { class, ..self }
+    }
+
     pub fn phrases(self) -> PhraseQuery<D, S, DF> {
         PhraseQuery {
             phrase: self.terms.into(),
-            highlighter: PhraseHighlighter::new(self.terms),
+            highlighter: PhraseHighlighter::new(self.terms, self.exact, self.class),
+            document_filter: self.document_filter,
+            spooky: PhantomData,
+        }
+    }
+
+    // all terms within `slop` extra tokens of each other; in the given order if `ordered` is set
+    pub fn near(self, slop: usize, ordered: bool) -> ProximityQuery<D, S, DF> {
+        ProximityQuery {
+            terms: self.terms.into(),
+            highlighter: ProximityHighlighter::new(self.terms, slop, ordered, self.class),
             document_filter: self.document_filter,
             spooky: PhantomData,
         }
@@ -94,11 +143,24 @@ where
     pub fn keywords(self) -> KeywordsQuery<D, S, DF> {
         KeywordsQuery {
             keywords: self.terms.into(),
-            highlighter: KeywordHighlighter::new(self.terms),
+            highlighter: KeywordHighlighter::new(self.terms, self.class),
             document_filter: self.document_filter,
             spooky: PhantomData,
         }
     }
+
+    // any of the terms a pattern expanded to, see FrozenTermMap::expand_prefix
+    pub fn expanded(self, truncated: bool) -> ExpansionQuery<D, S, DF> {
+        ExpansionQuery {
+            keywords: KeywordsQuery {
+                keywords: self.terms.into(),
+                highlighter: KeywordHighlighter::with_shared_class(self.terms, self.class),
+                document_filter: self.document_filter,
+                spooky: PhantomData,
+            },
+            truncated,
+        }
+    }
 }
 
 // what is this query being called by?
@@ -1,22 +1,34 @@
-use std::fmt;
+use std::{fmt, ops::Range};
 
 use logos::{Logos, SpannedIter};
 use smartstring::{LazyCompact, SmartString};
 
 use crate::{
-    query::{DocumentFilter, IntersectingQuery, PhraseQuery, QueryBuilder, UnionQuery},
+    analyzer::Analyzer,
+    dictionary::{is_wildcard_pattern, word_pattern, DEFAULT_EXPANSION_LIMIT},
+    query::{
+        DifferenceQuery, DocumentFilter, DocumentScopeQuery, IntersectingQuery, PhraseQuery,
+        QueryBuilder, UnionQuery,
+    },
     term_map::FrozenTermMap,
     DocumentMetadata, SentenceMetadata,
 };
 
 use super::{DynamicQuery, IntersectingPhraseQuery};
 
+// fuzzy matching past this many edits matches nearly everything, and gets slow doing it
+pub const MAX_FUZZY_DISTANCE: u32 = 2;
+
 #[derive(Debug, Logos, Clone, Copy)]
 pub enum QueryToken<'a> {
     #[regex(r#""([^"\\]|\\t|\\u|\\n|\\")*""#, |l| &l.slice()[1..l.slice().len()-1])]
     QuotedString(&'a str),
-    #[regex(r#"([^"\s)(]+)"#)]
+    #[regex(r#"([^"\s)(=\-~][^"\s)(~]*)"#)]
     Ident(&'a str),
+    #[token("=")]
+    Exact,
+    #[token("doc:")]
+    DocScope,
     #[token("(")]
     ParenOpen,
     #[token(")")]
@@ -25,6 +37,15 @@ pub enum QueryToken<'a> {
     And,
     #[regex(r#"OR|or|\|\|"#)]
     Or,
+    // uppercase only, so "do not panic" stays a phrase
+    #[token("NOT")]
+    Not,
+    #[token("-")]
+    Minus,
+    #[regex(r#"NEAR/[0-9]+"#, |l| l.slice()[5..].parse().ok())]
+    Near(u32),
+    #[regex(r#"~[0-9]+"#, |l| l.slice()[1..].parse().ok())]
+    Slop(u32),
     #[regex(r"\s", logos::skip)]
     InvalidToken,
 }
@@ -37,8 +58,9 @@ impl<'a> fmt::Display for QueryToken<'a> {
 
 pub type Spanned<Tok, Loc, Error> = Result<(Loc, Tok, Loc), Error>;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LexicalError {
-    InvalidToken,
+    InvalidToken { start: usize, end: usize },
 }
 
 pub struct Lexer<'input> {
@@ -59,19 +81,165 @@ impl<'input> Iterator for Lexer<'input> {
     fn next(&mut self) -> Option<Self::Item> {
         self.token_stream.next().map(|(token, span)| match token {
             Ok(t) => Ok((span.start, t, span.end)),
-            Err(_) => Err(LexicalError::InvalidToken),
+            Err(_) => Err(LexicalError::InvalidToken {
+                start: span.start,
+                end: span.end,
+            }),
         })
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryParseErrorKind {
+    // something that isn't a valid token at all, like an unterminated quote
+    InvalidToken,
+    UnexpectedToken,
+    // the query ended before it was complete
+    UnexpectedEnd,
+    // a fuzzy term allowing more than MAX_FUZZY_DISTANCE edits
+    FuzzyDistance,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryParseError {
+    pub kind: QueryParseErrorKind,
+    // byte range of the offending part of the query. empty, at the end, for UnexpectedEnd
+    pub span: Range<usize>,
+    // descriptions of the tokens that would've been accepted instead
+    pub expected: Vec<&'static str>,
+}
+
+impl fmt::Display for QueryParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            QueryParseErrorKind::InvalidToken => write!(f, "invalid token")?,
+            QueryParseErrorKind::UnexpectedToken => write!(f, "unexpected token")?,
+            QueryParseErrorKind::UnexpectedEnd => write!(f, "unexpected end of query")?,
+            QueryParseErrorKind::FuzzyDistance => {
+                write!(f, "fuzzy distance larger than {MAX_FUZZY_DISTANCE}")?
+            }
+        }
+
+        write!(f, " at {}..{}", self.span.start, self.span.end)?;
+
+        if !self.expected.is_empty() {
+            write!(f, ", expected one of: {}", self.expected.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for QueryParseError {}
+
+// peg describes expected tokens by their pattern in the grammar, e.g. "[QueryToken::Ident(v)]"
+fn describe_expected(pattern: &'static str) -> &'static str {
+    let name = pattern
+        .trim_start_matches("[QueryToken::")
+        .split(['(', ']'])
+        .next()
+        .unwrap_or(pattern);
+
+    match name {
+        "QuotedString" => "quoted string",
+        "Ident" => "word",
+        "ParenOpen" => "(",
+        "ParenClose" => ")",
+        "And" => "AND",
+        "Or" => "OR",
+        "Not" => "NOT",
+        "Minus" => "-",
+        "Exact" => "=",
+        "Near" => "NEAR/n",
+        "Slop" => "~n",
+        "DocScope" => "doc:",
+        _ => pattern,
+    }
+}
+
+pub fn parse_expression(query: &str) -> Result<Expression, QueryParseError> {
+    let mut spans = Vec::new();
+    let mut tokens = Vec::new();
+
+    for token in Lexer::new(query) {
+        match token {
+            Ok((start, token, end)) => {
+                // a slop right after a word makes it fuzzy
+                if let (Some(QueryToken::Ident(_)), QueryToken::Slop(distance)) =
+                    (tokens.last(), token)
+                {
+                    if distance > MAX_FUZZY_DISTANCE {
+                        return Err(QueryParseError {
+                            kind: QueryParseErrorKind::FuzzyDistance,
+                            span: start..end,
+                            expected: Vec::new(),
+                        });
+                    }
+                }
+
+                spans.push(start..end);
+                tokens.push(token);
+            }
+            Err(LexicalError::InvalidToken { start, end }) => {
+                return Err(QueryParseError {
+                    kind: QueryParseErrorKind::InvalidToken,
+                    span: start..end,
+                    expected: Vec::new(),
+                })
+            }
+        }
+    }
+
+    query_grammar::expression(&tokens).map_err(|e| {
+        let mut expected: Vec<&'static str> = e.expected.tokens().map(describe_expected).collect();
+        expected.sort_unstable();
+        expected.dedup();
+
+        match spans.get(e.location) {
+            Some(span) => QueryParseError {
+                kind: QueryParseErrorKind::UnexpectedToken,
+                span: span.clone(),
+                expected,
+            },
+            None => QueryParseError {
+                kind: QueryParseErrorKind::UnexpectedEnd,
+                span: query.len()..query.len(),
+                expected,
+            },
+        }
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Expression {
     Literal(SmartString<LazyCompact>),
+    // a literal matched against unstemmed terms only
+    Exact(SmartString<LazyCompact>),
     And(Box<Expression>, Box<Expression>),
     Or(Box<Expression>, Box<Expression>),
+    // only meaningful as an operand of an And, or of an Or that is one
+    Not(Box<Expression>),
+    // every word of the phrase within `slop` extra tokens of each other
+    Near {
+        phrase: SmartString<LazyCompact>,
+        slop: u32,
+        ordered: bool,
+    },
+    // every operand of the top level And has to match somewhere in the same document
+    Document(Box<Expression>),
+    // any term starting with this
+    Prefix(SmartString<LazyCompact>),
+    // any term matching a pattern with `*` and `?` wildcards
+    Wildcard(SmartString<LazyCompact>),
+    // any term within `distance` edits of this one
+    Fuzzy {
+        term: SmartString<LazyCompact>,
+        distance: u32,
+    },
 }
 
 impl Expression {
+    // each clause's highlights get their own class, numbered from 0 in the order they appear
     pub fn parse<
         'a,
         D: DocumentMetadata + 'a,
@@ -79,12 +247,47 @@ impl Expression {
         DF: DocumentFilter<D> + Clone + 'static,
     >(
         self,
-        terms: &FrozenTermMap,
+        terms: &FrozenTermMap<impl Analyzer>,
+        doc_filter: DF,
+        optimize: bool,
+    ) -> DynamicQuery<D, S, DF> {
+        self.build(terms, doc_filter, optimize, &mut 0)
+    }
+
+    // `classes` is the next unused highlight class
+    fn build<
+        'a,
+        D: DocumentMetadata + 'a,
+        S: SentenceMetadata + 'static,
+        DF: DocumentFilter<D> + Clone + 'static,
+    >(
+        self,
+        terms: &FrozenTermMap<impl Analyzer>,
         doc_filter: DF,
         optimize: bool,
+        classes: &mut u32,
     ) -> DynamicQuery<D, S, DF> {
+        let mut next_class = || {
+            *classes += 1;
+            *classes - 1
+        };
+
         match self {
             Expression::Literal(v) => QueryBuilder::start(&terms.tokenize_phrase(&v))
+                .class(next_class())
+                .filter_documents(doc_filter)
+                .phrases()
+                .into(),
+            Expression::Exact(v) if terms.has_exact_terms() => {
+                QueryBuilder::start(&terms.tokenize_exact_phrase(&v))
+                    .exact()
+                    .class(next_class())
+                    .filter_documents(doc_filter)
+                    .phrases()
+                    .into()
+            }
+            Expression::Exact(v) => QueryBuilder::start(&terms.tokenize_phrase(&v))
+                .class(next_class())
                 .filter_documents(doc_filter)
                 .phrases()
                 .into(),
@@ -93,19 +296,42 @@ impl Expression {
                     let lhs_filter = doc_filter.clone();
                     let lhs: PhraseQuery<D, S, _> =
                         QueryBuilder::start(&terms.tokenize_phrase(&lhs))
+                            .class(next_class())
                             .filter_documents(lhs_filter)
                             .phrases();
                     let rhs_filter = doc_filter.clone();
                     let rhs: PhraseQuery<D, S, _> =
                         QueryBuilder::start(&terms.tokenize_phrase(&rhs))
+                            .class(next_class())
                             .filter_documents(rhs_filter)
                             .phrases();
 
                     IntersectingPhraseQuery::from_iter([lhs, rhs], doc_filter).into()
                 }
+                // a AND (NOT b OR c) is (a AND NOT b) OR (a AND c)
+                (lhs, Expression::Or(x, y)) if x.negates() || y.negates() => Expression::Or(
+                    Box::new(Expression::And(Box::new(lhs.clone()), x)),
+                    Box::new(Expression::And(Box::new(lhs), y)),
+                )
+                .build(terms, doc_filter, optimize, classes),
+                (Expression::Or(x, y), rhs) if x.negates() || y.negates() => Expression::Or(
+                    Box::new(Expression::And(x, Box::new(rhs.clone()))),
+                    Box::new(Expression::And(y, Box::new(rhs))),
+                )
+                .build(terms, doc_filter, optimize, classes),
+                (lhs, Expression::Not(rhs)) => {
+                    let lhs = lhs.build(terms, doc_filter.clone(), optimize, classes);
+                    let rhs = rhs.build(terms, doc_filter, optimize, classes);
+                    DifferenceQuery::new(lhs, rhs).into()
+                }
+                (Expression::Not(lhs), rhs) => {
+                    let rhs = rhs.build(terms, doc_filter.clone(), optimize, classes);
+                    let lhs = lhs.build(terms, doc_filter, optimize, classes);
+                    DifferenceQuery::new(rhs, lhs).into()
+                }
                 (lhs, rhs) => {
-                    let lhs = lhs.parse(terms, doc_filter.clone(), optimize);
-                    let rhs = rhs.parse(terms, doc_filter.clone(), optimize);
+                    let lhs = lhs.build(terms, doc_filter.clone(), optimize, classes);
+                    let rhs = rhs.build(terms, doc_filter.clone(), optimize, classes);
                     IntersectingQuery::from_boxed([lhs, rhs], doc_filter).into()
                 }
             },
@@ -116,18 +342,22 @@ impl Expression {
 
                     if lhs_terms.len() == 1 && rhs_terms.len() == 1 {
                         let query_terms = vec![lhs_terms[0], rhs_terms[0]];
+                        let first_class = next_class();
+                        next_class();
+
                         QueryBuilder::start(&query_terms)
+                            .class(first_class)
                             .filter_documents(doc_filter)
                             .keywords()
                             .into()
                     } else {
-                        let _filter = doc_filter.clone();
-
                         let lhs = QueryBuilder::start(&lhs_terms)
+                            .class(next_class())
                             .filter_documents(doc_filter.clone())
                             .phrases();
 
                         let rhs = QueryBuilder::start(&rhs_terms)
+                            .class(next_class())
                             .filter_documents(doc_filter)
                             .phrases();
 
@@ -135,11 +365,82 @@ impl Expression {
                     }
                 }
                 (lhs, rhs) => {
-                    let lhs = lhs.parse(terms, doc_filter.clone(), optimize);
-                    let rhs = rhs.parse(terms, doc_filter, optimize);
+                    let lhs = lhs.build(terms, doc_filter.clone(), optimize, classes);
+                    let rhs = rhs.build(terms, doc_filter, optimize, classes);
                     UnionQuery::from_dynamic([lhs, rhs]).into()
                 }
             },
+            Expression::Near {
+                phrase,
+                slop,
+                ordered,
+            } => QueryBuilder::start(&terms.tokenize_phrase(&phrase))
+                .class(next_class())
+                .filter_documents(doc_filter)
+                .near(slop as usize, ordered)
+                .into(),
+            Expression::Prefix(v) => {
+                let expansion = terms.expand_prefix(&v, DEFAULT_EXPANSION_LIMIT);
+                QueryBuilder::start(&expansion.terms)
+                    .class(next_class())
+                    .filter_documents(doc_filter)
+                    .expanded(expansion.truncated)
+                    .into()
+            }
+            Expression::Wildcard(v) => {
+                let expansion = terms.expand_wildcard(&v, DEFAULT_EXPANSION_LIMIT);
+                QueryBuilder::start(&expansion.terms)
+                    .class(next_class())
+                    .filter_documents(doc_filter)
+                    .expanded(expansion.truncated)
+                    .into()
+            }
+            Expression::Fuzzy { term, distance } => {
+                let distance = distance.min(MAX_FUZZY_DISTANCE) as usize;
+                let expansion = terms.expand_fuzzy(&term, distance, DEFAULT_EXPANSION_LIMIT);
+                QueryBuilder::start(&expansion.terms)
+                    .class(next_class())
+                    .filter_documents(doc_filter)
+                    .expanded(expansion.truncated)
+                    .into()
+            }
+            Expression::Document(v) => {
+                let mut operands = Vec::new();
+                v.document_operands(&mut operands);
+
+                let queries: Vec<DynamicQuery<D, S, DF>> = operands
+                    .into_iter()
+                    .map(|operand| operand.build(terms, doc_filter.clone(), optimize, classes))
+                    .collect();
+
+                DocumentScopeQuery::from_dynamic(queries, doc_filter).into()
+            }
+            // a lone negation would have to scan every sentence, so it matches nothing instead
+            Expression::Not(_) => QueryBuilder::start(&[])
+                .filter_documents(doc_filter)
+                .phrases()
+                .into(),
+        }
+    }
+
+    // whether this is a negation, or an Or with one somewhere in its operands
+    fn negates(&self) -> bool {
+        match self {
+            Expression::Not(_) => true,
+            Expression::Or(lhs, rhs) => lhs.negates() || rhs.negates(),
+            _ => false,
+        }
+    }
+
+    // splits a chain of Ands into its operands. exclusions stay attached to the operand next to
+    // them, so they still apply per sentence
+    fn document_operands(self, operands: &mut Vec<Expression>) {
+        match self {
+            Expression::And(lhs, rhs) if !lhs.negates() && !rhs.negates() => {
+                lhs.document_operands(operands);
+                rhs.document_operands(operands);
+            }
+            v => operands.push(v),
         }
     }
 
@@ -159,25 +460,236 @@ peg::parser! {
 
         #[cache_left_rec]
         rule and() -> Expression
-            = l:and() [QueryToken::And] r:or() { Expression::And(Box::new(l), Box::new(r))}
+            = l:and() [QueryToken::And]? r:or() { Expression::And(Box::new(l), Box::new(r))}
             / or()
 
+        rule not() -> Expression
+            = ([QueryToken::Not] / [QueryToken::Minus]) v:negated() { Expression::Not(Box::new(v)) }
+
+        // only one word or phrase, so "-dragon dungeon" excludes dragon and requires dungeon
+        rule negated() -> Expression
+            = group()
+            / near()
+            / fuzzy()
+            / pattern()
+            / [QueryToken::Exact] v:term() { Expression::Exact(v) }
+            / v:term() { Expression::Literal(v) }
+
+        rule term() -> SmartString<LazyCompact>
+            = [QueryToken::QuotedString(v)] { v.into() }
+            / v:ident() ![QueryToken::Slop(_)] { v }
+
         #[cache_left_rec]
         rule or() -> Expression
-            = l:or() [QueryToken::Or] r:atom() { Expression::Or(Box::new(l), Box::new(r)) }
+            = l:or() [QueryToken::Or] r:unary() { Expression::Or(Box::new(l), Box::new(r)) }
+            / unary()
+
+        rule unary() -> Expression
+            = not()
             / atom()
 
         rule atom() -> Expression
-            = literal()
-            / [QueryToken::ParenOpen] v:and() [QueryToken::ParenClose] { v }
+            = words()
+            / group()
+
+        rule group() -> Expression
+            = [QueryToken::ParenOpen] v:and() [QueryToken::ParenClose] { v }
+            / [QueryToken::DocScope] [QueryToken::ParenOpen] v:and() [QueryToken::ParenClose] {
+                Expression::Document(Box::new(v))
+            }
+
+        // a run of terms, patterns and phrases next to each other, all of which have to match
+        rule words() -> Expression
+            = parts:(word()+) {
+                parts.into_iter().reduce(|l, r| Expression::And(Box::new(l), Box::new(r))).unwrap()
+            }
+
+        rule word() -> Expression
+            = near()
+            / fuzzy()
+            / pattern()
+            / literal()
+
+        rule near() -> Expression
+            = l:text() [QueryToken::Near(slop)] r:text() {
+                Expression::Near { phrase: format!("{l} {r}").into(), slop, ordered: false }
+            }
+            / [QueryToken::QuotedString(v)] [QueryToken::Slop(slop)] {
+                Expression::Near { phrase: v.into(), slop, ordered: true }
+            }
+
+        rule fuzzy() -> Expression
+            = v:ident() [QueryToken::Slop(distance)] {
+                Expression::Fuzzy { term: v, distance }
+            }
+
+        rule pattern() -> Expression
+            = [QueryToken::Ident(v)] {?
+                match word_pattern(v) {
+                    Some(p) => match p.strip_suffix('*') {
+                        Some(prefix) if !is_wildcard_pattern(prefix) => {
+                            Ok(Expression::Prefix(prefix.into()))
+                        }
+                        _ => Ok(Expression::Wildcard(p.as_ref().into())),
+                    },
+                    None => Err("pattern"),
+                }
+            }
 
         rule literal() -> Expression
-            = [QueryToken::QuotedString(v)] { Expression::Literal(v.into()) }
-            / l:(ident()+) { Expression::Literal(l.join(" ").into()) }
+            = [QueryToken::Exact] v:text() { Expression::Exact(v) }
+            / v:text() { Expression::Literal(v) }
+
+        rule text() -> SmartString<LazyCompact>
+            = [QueryToken::QuotedString(v)] { v.into() }
+            / l:(v:ident() ![QueryToken::Slop(_)] { v })+ { l.join(" ").into() }
 
+        // words with wildcards are patterns instead
         rule ident() -> SmartString<LazyCompact>
-            = [QueryToken::Ident(v)] { v.into() }
+            = [QueryToken::Ident(v)] {?
+                if word_pattern(v).is_some() { Err("word") } else { Ok(v.into()) }
+            }
     }
 }
 
 // pub struct Query
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::database;
+
+    fn literal(v: &str) -> Box<Expression> {
+        Box::new(Expression::Literal(v.into()))
+    }
+
+    fn not(v: &str) -> Box<Expression> {
+        Box::new(Expression::Not(literal(v)))
+    }
+
+    #[test]
+    fn test_lowercase_not_is_a_word() {
+        assert_eq!(
+            parse_expression("do not panic").unwrap(),
+            Expression::Literal("do not panic".into())
+        );
+    }
+
+    #[test]
+    fn test_negation() {
+        assert_eq!(
+            parse_expression("dragon -wizard").unwrap(),
+            Expression::And(literal("dragon"), not("wizard"))
+        );
+        assert_eq!(
+            parse_expression("dragon NOT wizard").unwrap(),
+            Expression::And(literal("dragon"), not("wizard"))
+        );
+        assert_eq!(
+            parse_expression("a AND NOT b OR c").unwrap(),
+            Expression::And(
+                literal("a"),
+                Box::new(Expression::Or(not("b"), literal("c")))
+            )
+        );
+    }
+
+    #[test]
+    fn test_leading_negation() {
+        assert_eq!(
+            parse_expression("-dragon dungeon").unwrap(),
+            Expression::And(not("dragon"), literal("dungeon"))
+        );
+
+        let docs = [
+            "The dragon sleeps in the dungeon.",
+            "The knight walks in the dungeon.",
+            "The dragon flies.",
+        ];
+        let db = database("parser_negation", &docs, |_| {});
+
+        let matching = |query: &str| -> Vec<u32> {
+            let query = db.parse_query(query, (), true).unwrap();
+            db.query(&query).map(|result| result.id.doc).collect()
+        };
+
+        assert_eq!(matching("dungeon -dragon"), [2]);
+        assert_eq!(matching("NOT dragon AND dungeon"), [2]);
+        assert_eq!(matching("-dragon dungeon"), [2]);
+        assert_eq!(matching("dungeon AND NOT dragon OR sleeps"), [1, 2]);
+        assert_eq!(matching("(NOT dragon OR sleeps) AND dungeon"), [1, 2]);
+        assert_eq!(matching("doc:(-dragon dungeon)"), [2]);
+        assert_eq!(matching("doc:(knight AND -dragon dungeon)"), [2]);
+    }
+
+    #[test]
+    fn test_patterns_next_to_words() {
+        assert_eq!(
+            parse_expression("where is the wizard?").unwrap(),
+            Expression::Literal("where is the wizard?".into())
+        );
+        assert_eq!(
+            parse_expression("dragon slayer*").unwrap(),
+            Expression::And(
+                literal("dragon"),
+                Box::new(Expression::Prefix("slayer".into()))
+            )
+        );
+        assert_eq!(
+            parse_expression("drag* slayer").unwrap(),
+            Expression::And(
+                Box::new(Expression::Prefix("drag".into())),
+                literal("slayer")
+            )
+        );
+        assert_eq!(
+            parse_expression(r"w?zard wizard\?").unwrap(),
+            Expression::And(
+                Box::new(Expression::Wildcard("w?zard".into())),
+                Box::new(Expression::Wildcard("wizard?".into()))
+            )
+        );
+    }
+
+    #[test]
+    fn test_near() {
+        assert_eq!(
+            parse_expression("dragon NEAR/3 wizard").unwrap(),
+            Expression::Near {
+                phrase: "dragon wizard".into(),
+                slop: 3,
+                ordered: false
+            }
+        );
+        assert_eq!(
+            parse_expression("\"red dragon\"~2").unwrap(),
+            Expression::Near {
+                phrase: "red dragon".into(),
+                slop: 2,
+                ordered: true
+            }
+        );
+    }
+
+    #[test]
+    fn test_error_spans() {
+        let unterminated = parse_expression("\"dragon").unwrap_err();
+        assert_eq!(unterminated.kind, QueryParseErrorKind::InvalidToken);
+        assert_eq!(unterminated.span, 0..7);
+
+        let dangling = parse_expression("dragon AND").unwrap_err();
+        assert_eq!(dangling.kind, QueryParseErrorKind::UnexpectedEnd);
+        assert_eq!(dangling.span, 10..10);
+        assert!(dangling.expected.contains(&"word"));
+
+        let unexpected = parse_expression("dragon )").unwrap_err();
+        assert_eq!(unexpected.kind, QueryParseErrorKind::UnexpectedToken);
+        assert_eq!(unexpected.span, 7..8);
+        assert!(unexpected.expected.contains(&"AND"));
+
+        let fuzzy = parse_expression("dragon~3 OR \"red dragon\"~3").unwrap_err();
+        assert_eq!(fuzzy.kind, QueryParseErrorKind::FuzzyDistance);
+        assert_eq!(fuzzy.span, 6..8);
+        assert!(parse_expression("dragon~2 OR \"red dragon\"~3").is_ok());
+    }
+}
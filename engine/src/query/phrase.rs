@@ -3,11 +3,11 @@ use std::marker::PhantomData;
 use memchr::memmem::Finder;
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use rkyv::Archive;
-use storage::Storage;
 
 use crate::{
     highlight::Highlighter,
     id_list::SentenceIdList,
+    postings::Postings,
     searcher::{SearchEngine, SearchResult},
     sentence::{ArchivedSentence, SentenceId, SentenceRange},
     DocumentMetadata, SentenceMetadata,
@@ -35,45 +35,12 @@ where
     DF: DocumentFilter<D> + Sync + Send,
 {
     fn find_sentence_ids(&self, db: &SearchEngine<D, S>, _caller: CallerType) -> SentenceIdList {
-        let mut term_sets: Vec<&[SentenceId]> = self
-            .phrase
-            .par_iter()
-            .map(|term| db.index.get(term).unwrap_or(&[]))
-            .collect();
-
-        if term_sets.is_empty() {
-            return SentenceIdList { ids: Vec::new() };
-        }
-
-        term_sets.sort_by_key(|v| v.len());
-
-        let mut sentence_ids = SentenceIdList::from_slice(term_sets[0]);
-
-        match DF::needed() {
-            true if term_sets.len() > 1 => {
-                for set in &term_sets[1..] {
-                    sentence_ids.retain(|v| {
-                        self.document_filter
-                            .filter_document(unsafe { db.doc_meta.get_unchecked(v.doc as usize) })
-                            && set.binary_search(v).is_ok()
-                    });
-                }
-            }
-            false if term_sets.len() > 1 => {
-                for set in &term_sets[1..] {
-                    sentence_ids.retain(|v| set.binary_search(v).is_ok());
-                }
-            }
-            true => {
-                sentence_ids.retain(|id| {
-                    self.document_filter
-                        .filter_document(unsafe { db.doc_meta.get_unchecked(id.doc as usize) })
-                });
-            }
-            false => {}
-        }
+        find_phrase_candidates(db, &self.phrase, &self.document_filter)
+    }
 
-        sentence_ids
+    fn exact_ids(&self) -> bool {
+        // a single term's posting list is exactly the set of sentences containing it
+        self.phrase.len() <= 1
     }
 
     fn filter_map(&self, result: &mut SearchResult<'_, S>) -> bool {
@@ -81,22 +48,121 @@ where
         !result.highlighted_parts.is_empty()
     }
 
+    fn collect_terms(&self, terms: &mut Vec<u32>) {
+        terms.extend(self.phrase.iter().filter(|term| **term != 0));
+    }
+
     fn find_highlights(&self, _sentence: &mut SearchResult<'_, S>) {
         // already highlighted by filter_map
     }
 }
 
+// sentences containing every (non-stopword) term of the phrase, in any order
+pub(crate) fn find_phrase_candidates<D, S, DF>(
+    db: &SearchEngine<D, S>,
+    phrase: &[u32],
+    document_filter: &DF,
+) -> SentenceIdList
+where
+    D: DocumentMetadata,
+    S: SentenceMetadata,
+    DF: DocumentFilter<D>,
+{
+    let mut term_sets: Vec<Postings> = phrase
+        .par_iter()
+        .filter_map(|term| phrase_term_set(db, *term))
+        .collect();
+
+    if term_sets.is_empty() {
+        return scan_candidates(db, phrase, document_filter);
+    }
+
+    term_sets.sort_by_key(|v| v.len());
+
+    let mut sentence_ids = SentenceIdList::from_slice(&term_sets[0].decode());
+
+    for set in &term_sets[1..] {
+        if sentence_ids.is_empty() {
+            break;
+        }
+
+        sentence_ids.intersect_postings(*set);
+    }
+
+    sentence_ids.filter_documents(document_filter, db);
+
+    sentence_ids
+}
+
+// the posting list for a phrase term, or None if it should be skipped when finding candidates.
+// known terms without a posting list are stopwords - they're still checked by the highlighter.
+#[inline(always)]
+pub(crate) fn phrase_term_set<D: DocumentMetadata, S: SentenceMetadata>(
+    db: &SearchEngine<D, S>,
+    term: u32,
+) -> Option<Postings<'_>> {
+    match db.index.get(&term) {
+        Some(set) => Some(set),
+        None if term != 0 => None,
+        None => Some(Postings::Raw(&[])),
+    }
+}
+
+// sentences containing every term, found by going through all of them. only for phrases made up
+// of stopwords alone, which have no posting lists to go by
+pub(crate) fn scan_candidates<D, S, DF>(
+    db: &SearchEngine<D, S>,
+    terms: &[u32],
+    document_filter: &DF,
+) -> SentenceIdList
+where
+    D: DocumentMetadata,
+    S: SentenceMetadata,
+    DF: DocumentFilter<D>,
+{
+    let mut ids = Vec::new();
+
+    for doc in 0..db.stats.doc_lengths.len() as u32 {
+        if db.stats.doc_len(doc) == 0 {
+            continue;
+        }
+
+        for sentence in 0.. {
+            let id = SentenceId::new(doc, sentence);
+            let Some(sentence) = db.sentences.get(&id) else {
+                break;
+            };
+
+            if terms
+                .iter()
+                .all(|term| sentence.terms_by_value.contains_key(term))
+            {
+                ids.push(id);
+            }
+        }
+    }
+
+    let mut ids = SentenceIdList { ids };
+    ids.filter_documents(document_filter, db);
+    ids
+}
+
 #[derive(Clone)]
 pub struct PhraseHighlighter {
     phrase: Vec<u32>,
     finder: Finder<'static>,
+    // match against Sentence::exact_terms instead of Sentence::terms
+    exact: bool,
+    class: u32,
 }
 
 impl PhraseHighlighter {
-    pub fn new(phrase: &[u32]) -> PhraseHighlighter {
+    pub fn new(phrase: &[u32], exact: bool, class: u32) -> PhraseHighlighter {
         PhraseHighlighter {
             phrase: Vec::from(phrase),
             finder: Finder::new(bytemuck::cast_slice(phrase)).into_owned(),
+            exact,
+            class,
         }
     }
 }
@@ -108,7 +174,11 @@ impl<'a> Highlighter<'a> for PhraseHighlighter {
     ) -> Vec<SentenceRange> {
         let mut highlights = Vec::with_capacity(8);
 
-        let term_bytes: &[u8] = bytemuck::cast_slice(&sentence.terms);
+        let term_bytes: &[u8] = if self.exact {
+            bytemuck::cast_slice(&sentence.exact_terms)
+        } else {
+            bytemuck::cast_slice(&sentence.terms)
+        };
 
         for idx in self.finder.find_iter(term_bytes) {
             let idx = idx / 4;
@@ -118,6 +188,7 @@ impl<'a> Highlighter<'a> for PhraseHighlighter {
             highlights.push(SentenceRange {
                 start: start_token.start as usize,
                 end: end_token.end as usize,
+                class: self.class,
             });
         }
 
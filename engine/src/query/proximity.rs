@@ -0,0 +1,204 @@
+use std::marker::PhantomData;
+
+use rkyv::Archive;
+
+use crate::{
+    highlight::{collapse_overlapped_ranges, Highlighter},
+    id_list::SentenceIdList,
+    searcher::{SearchEngine, SearchResult},
+    sentence::{ArchivedSentence, SentenceRange},
+    DocumentMetadata, SentenceMetadata,
+};
+
+use super::{phrase::find_phrase_candidates, CallerType, DocumentFilter, Query};
+
+// matches sentences where all terms occur within `slop` extra tokens of each other
+#[derive(Clone)]
+pub struct ProximityQuery<D, S, DF>
+where
+    D: DocumentMetadata,
+    S: SentenceMetadata,
+    DF: DocumentFilter<D>,
+{
+    pub(crate) terms: Vec<u32>,
+    pub(crate) highlighter: ProximityHighlighter,
+    pub(crate) document_filter: DF,
+    pub(crate) spooky: PhantomData<(D, S)>,
+}
+
+impl<D, S, DF> Query<D, S> for ProximityQuery<D, S, DF>
+where
+    D: DocumentMetadata,
+    S: SentenceMetadata,
+    DF: DocumentFilter<D>,
+{
+    fn find_sentence_ids(&self, db: &SearchEngine<D, S>, _caller: CallerType) -> SentenceIdList {
+        find_phrase_candidates(db, &self.terms, &self.document_filter)
+    }
+
+    fn filter_map(&self, result: &mut SearchResult<'_, S>) -> bool {
+        result.highlighted_parts = self.highlighter.highlight(result.sentence);
+        !result.highlighted_parts.is_empty()
+    }
+
+    fn collect_terms(&self, terms: &mut Vec<u32>) {
+        terms.extend(self.terms.iter().filter(|term| **term != 0));
+    }
+
+    fn find_highlights(&self, _sentence: &mut SearchResult<'_, S>) {
+        // already highlighted by filter_map
+    }
+}
+
+#[derive(Clone)]
+pub struct ProximityHighlighter {
+    terms: Vec<u32>,
+    slop: usize,
+    ordered: bool,
+    class: u32,
+}
+
+impl ProximityHighlighter {
+    pub fn new(terms: &[u32], slop: usize, ordered: bool, class: u32) -> ProximityHighlighter {
+        let mut terms = Vec::from(terms);
+
+        // repeating a term only matters when order does
+        if !ordered {
+            terms.sort_unstable();
+            terms.dedup();
+        }
+
+        ProximityHighlighter {
+            terms,
+            slop,
+            ordered,
+            class,
+        }
+    }
+
+    // token positions of each term, or None if any of them is missing
+    fn positions<S: Archive>(&self, sentence: &ArchivedSentence<S>) -> Option<Vec<Vec<usize>>> {
+        self.terms
+            .iter()
+            .map(|term| {
+                sentence
+                    .terms_by_value
+                    .get(term)
+                    .map(|positions| positions.iter().map(|v| *v as usize).collect())
+            })
+            .collect()
+    }
+
+    // extra tokens in a match spanning first..=last
+    #[inline(always)]
+    fn slop_of(&self, first: usize, last: usize) -> usize {
+        (last - first + 1).saturating_sub(self.terms.len())
+    }
+
+    fn ordered_matches(&self, positions: &[Vec<usize>]) -> Vec<usize> {
+        let mut matched = Vec::new();
+        let mut current = Vec::with_capacity(positions.len());
+
+        'starts: for &first in &positions[0] {
+            current.clear();
+            current.push(first);
+
+            for term_positions in &positions[1..] {
+                let prev = *current.last().unwrap();
+                let next = term_positions.partition_point(|&p| p <= prev);
+
+                // no later start can succeed either
+                let Some(&pos) = term_positions.get(next) else {
+                    break 'starts;
+                };
+
+                current.push(pos);
+            }
+
+            if self.slop_of(first, *current.last().unwrap()) <= self.slop {
+                matched.extend_from_slice(&current);
+            }
+        }
+
+        matched
+    }
+
+    fn unordered_matches(&self, positions: &[Vec<usize>]) -> Vec<usize> {
+        // (position, term idx), in sentence order
+        let mut occurrences: Vec<(usize, usize)> = positions
+            .iter()
+            .enumerate()
+            .flat_map(|(term_idx, term_positions)| {
+                term_positions.iter().map(move |&p| (p, term_idx))
+            })
+            .collect();
+        occurrences.sort_unstable();
+
+        let mut matched = Vec::new();
+        let mut counts = vec![0usize; positions.len()];
+        let mut covered = 0;
+        let mut left = 0;
+
+        // slide a window over the occurrences, checking every window that covers all terms
+        for right in 0..occurrences.len() {
+            let (_, term_idx) = occurrences[right];
+            if counts[term_idx] == 0 {
+                covered += 1;
+            }
+            counts[term_idx] += 1;
+
+            while covered == positions.len() {
+                if self.slop_of(occurrences[left].0, occurrences[right].0) <= self.slop {
+                    matched.extend(occurrences[left..=right].iter().map(|(pos, _)| *pos));
+                }
+
+                let (_, term_idx) = occurrences[left];
+                counts[term_idx] -= 1;
+                if counts[term_idx] == 0 {
+                    covered -= 1;
+                }
+                left += 1;
+            }
+        }
+
+        matched
+    }
+}
+
+impl<'a> Highlighter<'a> for ProximityHighlighter {
+    fn highlight<'b, S: Archive>(
+        &'a self,
+        sentence: &'b ArchivedSentence<S>,
+    ) -> Vec<SentenceRange> {
+        if self.terms.is_empty() {
+            return Vec::new();
+        }
+
+        let Some(positions) = self.positions(sentence) else {
+            return Vec::new();
+        };
+
+        let mut matched = if self.ordered {
+            self.ordered_matches(&positions)
+        } else {
+            self.unordered_matches(&positions)
+        };
+
+        matched.sort_unstable();
+        matched.dedup();
+
+        let highlights: Vec<SentenceRange> = matched
+            .into_iter()
+            .map(|idx| {
+                let token = &sentence.tokens[idx];
+                SentenceRange {
+                    start: token.start as usize,
+                    end: token.end as usize,
+                    class: self.class,
+                }
+            })
+            .collect();
+
+        collapse_overlapped_ranges(&highlights)
+    }
+}
@@ -63,6 +63,10 @@ impl<D: DocumentMetadata, S: SentenceMetadata, DF: DocumentFilter<D>> Query<D, S
         SentenceIdList { ids: sets }
     }
 
+    fn exact_ids(&self) -> bool {
+        self.queries.iter().all(|query| query.exact_ids())
+    }
+
     fn filter_map(&self, result: &mut SearchResult<'_, S>) -> bool {
         let mut highlights = Vec::new();
 
@@ -93,4 +97,10 @@ impl<D: DocumentMetadata, S: SentenceMetadata, DF: DocumentFilter<D>> Query<D, S
         highlights.par_sort_by_key(|v| v.start);
         result.highlighted_parts = collapse_overlapped_ranges(&highlights);
     }
+
+    fn collect_terms(&self, terms: &mut Vec<u32>) {
+        for query in &self.queries {
+            query.collect_terms(terms);
+        }
+    }
 }
@@ -0,0 +1,188 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use rkyv::Archive;
+
+use crate::{postings::Postings, sentence::ArchivedSentence};
+
+// corpus-wide numbers needed for scoring, computed when building the database
+#[derive(Default, Clone, Debug)]
+#[cfg_attr(feature = "persistence", derive(serde::Serialize, serde::Deserialize))]
+pub struct CorpusStats {
+    pub sentences: u64,
+    pub documents: u64,
+    pub tokens: u64,
+    // token count of each document, indexed by document id
+    pub doc_lengths: Vec<u32>,
+}
+
+impl CorpusStats {
+    pub fn avg_sentence_len(&self) -> f32 {
+        self.tokens as f32 / std::cmp::max(self.sentences, 1) as f32
+    }
+
+    pub fn avg_doc_len(&self) -> f32 {
+        self.tokens as f32 / std::cmp::max(self.documents, 1) as f32
+    }
+
+    pub fn doc_len(&self, doc: u32) -> u32 {
+        self.doc_lengths.get(doc as usize).copied().unwrap_or(0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bm25 {
+    pub k1: f32,
+    pub b: f32,
+}
+
+impl Default for Bm25 {
+    fn default() -> Self {
+        Bm25 { k1: 1.2, b: 0.75 }
+    }
+}
+
+impl Bm25 {
+    // `matching` is how many of `total` units (sentences or documents) contain the term
+    #[inline(always)]
+    pub fn idf(&self, matching: u64, total: u64) -> f32 {
+        let (n, total) = (matching as f32, total as f32);
+        (1.0 + (total - n + 0.5) / (n + 0.5)).ln()
+    }
+
+    #[inline(always)]
+    pub fn term_score(&self, idf: f32, tf: u32, len: u32, avg_len: f32) -> f32 {
+        let tf = tf as f32;
+        let norm = 1.0 - self.b + self.b * (len as f32 / avg_len.max(1.0));
+        idf * (tf * (self.k1 + 1.0)) / (tf + self.k1 * norm)
+    }
+
+    // `terms` are (term, idf) pairs
+    pub fn score_sentence<S: Archive>(
+        &self,
+        terms: &[(u32, f32)],
+        sentence: &ArchivedSentence<S>,
+        stats: &CorpusStats,
+    ) -> f32 {
+        let len = sentence.tokens.len() as u32;
+        let avg_len = stats.avg_sentence_len();
+
+        terms
+            .iter()
+            .filter_map(|(term, idf)| {
+                let tf = sentence.terms_by_value.get(term)?.len() as u32;
+                Some(self.term_score(*idf, tf, len, avg_len))
+            })
+            .sum()
+    }
+}
+
+// number of distinct documents in a sorted posting list
+pub(crate) fn document_frequency(postings: Postings) -> u64 {
+    let mut count = 0;
+    let mut last = None;
+
+    for id in postings.iter() {
+        if last != Some(id.doc) {
+            count += 1;
+            last = Some(id.doc);
+        }
+    }
+
+    count
+}
+
+pub(crate) struct Scored<T> {
+    pub score: f32,
+    pub value: T,
+}
+
+impl<T> PartialEq for Scored<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.score.total_cmp(&other.score) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Scored<T> {}
+
+impl<T> PartialOrd for Scored<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// reversed, so a BinaryHeap of these keeps the lowest score on top
+impl<T> Ord for Scored<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.score.total_cmp(&self.score)
+    }
+}
+
+// keeps the k highest scoring values
+pub(crate) struct TopK<T> {
+    k: usize,
+    heap: BinaryHeap<Scored<T>>,
+}
+
+impl<T> TopK<T> {
+    pub fn new(k: usize) -> TopK<T> {
+        TopK {
+            k,
+            // k can be anything up to usize::MAX, so the heap grows past this as needed
+            heap: BinaryHeap::with_capacity(k.min(1024) + 1),
+        }
+    }
+
+    // whether a value with this score would make it in
+    #[inline(always)]
+    pub fn accepts(&self, score: f32) -> bool {
+        self.heap.len() < self.k || self.heap.peek().is_some_and(|min| score > min.score)
+    }
+
+    pub fn push(&mut self, score: f32, value: T) {
+        if !self.accepts(score) {
+            return;
+        }
+
+        self.heap.push(Scored { score, value });
+
+        if self.heap.len() > self.k {
+            self.heap.pop();
+        }
+    }
+
+    // highest score first
+    pub fn into_sorted_vec(self) -> Vec<Scored<T>> {
+        // sorted by Ord, which is reversed
+        self.heap.into_sorted_vec()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Bm25, TopK};
+
+    #[test]
+    fn test_term_score_ordering() {
+        let bm25 = Bm25::default();
+        let idf = bm25.idf(1, 10);
+
+        // rarer terms, more occurrences and shorter units all score higher
+        assert!(bm25.idf(1, 10) > bm25.idf(5, 10));
+        assert!(bm25.term_score(idf, 2, 10, 10.0) > bm25.term_score(idf, 1, 10, 10.0));
+        assert!(bm25.term_score(idf, 1, 5, 10.0) > bm25.term_score(idf, 1, 20, 10.0));
+    }
+
+    #[test]
+    fn test_top_k() {
+        let mut top = TopK::new(2);
+        for (score, value) in [(1.0, 'a'), (3.0, 'b'), (2.0, 'c'), (0.5, 'd')] {
+            top.push(score, value);
+        }
+        let values: Vec<char> = top.into_sorted_vec().into_iter().map(|v| v.value).collect();
+        assert_eq!(values, ['b', 'c']);
+
+        let mut top = TopK::new(usize::MAX);
+        top.push(1.0, 'a');
+        assert_eq!(top.into_sorted_vec().len(), 1);
+    }
+}
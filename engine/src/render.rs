@@ -0,0 +1,259 @@
+use std::borrow::Cow;
+
+use rkyv::Archive;
+
+use crate::{searcher::Snippet, sentence::SentencePart};
+
+// turns highlighted sentence parts into text for some output format
+pub trait Renderer {
+    fn normal(&self, text: &str, out: &mut String);
+
+    // `class` is the highlight class of the query clause that matched
+    fn highlight(&self, text: &str, class: u32, out: &mut String);
+
+    fn ellipsis(&self, out: &mut String) {
+        out.push('…');
+    }
+
+    // put between the sentences of a snippet
+    fn separator(&self, out: &mut String) {
+        out.push(' ');
+    }
+
+    fn render_into(&self, parts: &[SentencePart<'_>], out: &mut String) {
+        for part in parts {
+            match part {
+                SentencePart::Normal(text) => self.normal(text, out),
+                SentencePart::Highlight(text, class) => self.highlight(text, *class, out),
+                SentencePart::Ellipsis => self.ellipsis(out),
+            }
+        }
+    }
+
+    fn render(&self, parts: &[SentencePart<'_>]) -> String {
+        let mut out = String::new();
+        self.render_into(parts, &mut out);
+        out
+    }
+
+    fn render_snippet<M: Archive>(&self, snippet: &Snippet<'_, M>) -> String {
+        let mut out = String::new();
+
+        for (idx, sentence) in snippet.sentences.iter().enumerate() {
+            if idx > 0 {
+                self.separator(&mut out);
+            }
+
+            self.render_into(&sentence.highlights(), &mut out);
+        }
+
+        out
+    }
+}
+
+// escapes text and wraps highlights in `tag`, <mark> by default. with a class prefix, each
+// highlight also gets a css class of the prefix followed by its highlight class, e.g. "hl-0"
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Html {
+    pub tag: Cow<'static, str>,
+    pub class_prefix: Option<Cow<'static, str>>,
+}
+
+impl Html {
+    pub fn new() -> Html {
+        Html::default()
+    }
+
+    pub fn with_tag(tag: impl Into<Cow<'static, str>>) -> Html {
+        Html {
+            tag: tag.into(),
+            class_prefix: None,
+        }
+    }
+
+    pub fn with_class_prefix(self, prefix: impl Into<Cow<'static, str>>) -> Html {
+        Html {
+            class_prefix: Some(prefix.into()),
+            ..self
+        }
+    }
+}
+
+impl Default for Html {
+    fn default() -> Self {
+        Html::with_tag("mark")
+    }
+}
+
+pub fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+impl Renderer for Html {
+    fn normal(&self, text: &str, out: &mut String) {
+        escape_html(text, out);
+    }
+
+    fn highlight(&self, text: &str, class: u32, out: &mut String) {
+        out.push('<');
+        out.push_str(&self.tag);
+
+        if let Some(prefix) = &self.class_prefix {
+            out.push_str(" class=\"");
+            escape_html(prefix, out);
+            out.push_str(&class.to_string());
+            out.push('"');
+        }
+
+        out.push('>');
+        escape_html(text, out);
+        out.push_str("</");
+        out.push_str(&self.tag);
+        out.push('>');
+    }
+
+    fn ellipsis(&self, out: &mut String) {
+        out.push_str("&hellip;");
+    }
+}
+
+// terminal escape codes around highlights. each highlight class gets the next style of the
+// palette, wrapping around
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ansi {
+    pub styles: Vec<Cow<'static, str>>,
+    pub end: Cow<'static, str>,
+}
+
+impl Ansi {
+    pub fn new() -> Ansi {
+        Ansi::default()
+    }
+
+    // `sgr` is a select graphic rendition parameter list, like "1;33"
+    pub fn with_style(sgr: &str) -> Ansi {
+        Ansi::with_palette(&[sgr])
+    }
+
+    pub fn with_palette(sgrs: &[&str]) -> Ansi {
+        Ansi {
+            styles: sgrs
+                .iter()
+                .map(|sgr| format!("\x1b[{sgr}m").into())
+                .collect(),
+            end: "\x1b[0m".into(),
+        }
+    }
+}
+
+impl Default for Ansi {
+    // bold yellow, cyan, magenta, green
+    fn default() -> Self {
+        Ansi::with_palette(&["1;33", "1;36", "1;35", "1;32"])
+    }
+}
+
+impl Renderer for Ansi {
+    fn normal(&self, text: &str, out: &mut String) {
+        out.push_str(text);
+    }
+
+    fn highlight(&self, text: &str, class: u32, out: &mut String) {
+        if self.styles.is_empty() {
+            out.push_str(text);
+            return;
+        }
+
+        out.push_str(&self.styles[class as usize % self.styles.len()]);
+        out.push_str(text);
+        out.push_str(&self.end);
+    }
+}
+
+// highlights in bold, with markdown syntax in the text escaped
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Markdown;
+
+pub fn escape_markdown(text: &str, out: &mut String) {
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '|' | '~'
+        ) {
+            out.push('\\');
+        }
+
+        out.push(c);
+    }
+}
+
+impl Renderer for Markdown {
+    fn normal(&self, text: &str, out: &mut String) {
+        escape_markdown(text, out);
+    }
+
+    fn highlight(&self, text: &str, _class: u32, out: &mut String) {
+        // emphasis can't start or end with whitespace, so it stays outside
+        let trimmed = text.trim();
+        if trimmed.is_empty() {
+            out.push_str(text);
+            return;
+        }
+
+        let start = text.len() - text.trim_start().len();
+        out.push_str(&text[..start]);
+        out.push_str("**");
+        escape_markdown(trimmed, out);
+        out.push_str("**");
+        out.push_str(&text[start + trimmed.len()..]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        render::{escape_html, Html, Markdown, Renderer},
+        sentence::SentencePart,
+    };
+
+    #[test]
+    fn test_escape_html() {
+        let mut out = String::new();
+        escape_html(r#"<a href="x">Tom & 'Jerry'</a>"#, &mut out);
+        assert_eq!(
+            out,
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; &#39;Jerry&#39;&lt;/a&gt;"
+        );
+
+        let parts = [
+            SentencePart::Normal("1 < 2 & "),
+            SentencePart::Highlight("\"3\" > '2'", 1),
+        ];
+        assert_eq!(
+            Html::new().with_class_prefix("hl-").render(&parts),
+            "1 &lt; 2 &amp; <mark class=\"hl-1\">&quot;3&quot; &gt; &#39;2&#39;</mark>"
+        );
+    }
+
+    #[test]
+    fn test_markdown_highlights() {
+        let parts = [
+            SentencePart::Normal("see "),
+            SentencePart::Highlight(" *bold* [link]_x_ ", 0),
+            SentencePart::Normal("#1"),
+        ];
+        assert_eq!(
+            Markdown.render(&parts),
+            r"see  **\*bold\* \[link\]\_x\_** \#1"
+        );
+    }
+}
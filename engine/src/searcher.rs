@@ -1,28 +1,108 @@
+use std::ops::Range;
+
 use rkyv::Archive;
 
-use crate::query::CallerType;
-use crate::{highlight::highlight_by_ranges, query::Query};
-use crate::{sentence::*, CopyableRange, DocumentMetadata, SentenceMetadata};
+use crate::highlight::{
+    convert_ranges, highlight_by_ranges, snippet_by_ranges, OffsetUnit, SnippetBudget,
+};
+use crate::postings::{PostingIndex, Postings};
+use crate::query::{CallerType, Query};
+use crate::ranking::{document_frequency, Bm25, CorpusStats, TopK};
+use crate::{sentence::*, DocumentMetadata, SentenceMetadata, Token};
 
-use storage::{MultiMap, RkyvMap, SimpleStorage};
+use storage::{RkyvMap, SimpleStorage};
 
 pub struct SearchEngine<DM: DocumentMetadata, SM: SentenceMetadata> {
     pub(crate) doc_meta: SimpleStorage<DM>,
     pub(crate) sentences: RkyvMap<SentenceId, Sentence<SM>>,
-    pub(crate) index: MultiMap<u32, SentenceId>,
+    pub(crate) index: PostingIndex,
+    pub(crate) stats: CorpusStats,
 }
 
 #[derive(Clone)]
 pub struct SearchResult<'a, M: Archive> {
     pub id: SentenceId,
-    pub highlighted_parts: Vec<CopyableRange>,
+    pub highlighted_parts: Vec<SentenceRange>,
     pub sentence: &'a ArchivedSentence<M>,
+    // relevance score, for ranked queries. always zero otherwise
+    pub score: f32,
+}
+
+pub struct Page<'a, M: Archive> {
+    pub results: Vec<SearchResult<'a, M>>,
+    // matches across all pages
+    pub total_hits: usize,
+    // whether total_hits is exact. if not, it's an upper bound
+    pub exact_total: bool,
+}
+
+pub struct DocumentHits<'a, D: Archive, M: Archive> {
+    pub id: u32,
+    pub document: &'a D::Archived,
+    // matching sentences, in order
+    pub sentences: Vec<SearchResult<'a, M>>,
+}
+
+impl<'a, D: Archive, M: Archive> DocumentHits<'a, D, M> {
+    pub fn hits(&self) -> usize {
+        self.sentences.len()
+    }
+}
+
+pub struct DocumentPage<'a, D: Archive, M: Archive> {
+    pub documents: Vec<DocumentHits<'a, D, M>>,
+    // matching documents across all pages
+    pub total_documents: usize,
+    // whether total_documents is exact. if not, it's an upper bound
+    pub exact_total: bool,
+}
+
+// a run of consecutive sentences from one document, around one or more hits
+pub struct Snippet<'a, M: Archive> {
+    pub doc: u32,
+    // hits and their context, in order. context sentences have no highlights
+    pub sentences: Vec<SearchResult<'a, M>>,
+    // indices into `sentences` of the hits themselves
+    pub hits: Vec<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RankedDocument {
+    pub id: u32,
+    pub score: f32,
+    // how many of the document's sentences matched
+    pub hits: usize,
 }
 
 impl<'a, M: Archive> SearchResult<'a, M> {
     pub fn highlights(&'a self) -> Vec<SentencePart> {
         highlight_by_ranges(&self.highlighted_parts, &self.sentence.text)
     }
+
+    // highlight ranges, with offsets counted in `unit` instead of bytes
+    pub fn highlight_ranges(&self, unit: OffsetUnit) -> Vec<SentenceRange> {
+        convert_ranges(&self.highlighted_parts, &self.sentence.text, unit)
+    }
+
+    // the highlighted sentence, trimmed to `budget` around its highlights
+    pub fn snippet(&'a self, budget: SnippetBudget) -> Vec<SentencePart<'a>> {
+        let tokens: Vec<Token> = self
+            .sentence
+            .tokens
+            .iter()
+            .map(|token| Token {
+                start: token.start as usize,
+                end: token.end as usize,
+            })
+            .collect();
+
+        snippet_by_ranges(
+            &self.highlighted_parts,
+            &tokens,
+            &self.sentence.text,
+            budget,
+        )
+    }
 }
 
 impl<D, S> SearchEngine<D, S>
@@ -41,6 +121,7 @@ where
                 id: sentence_id,
                 highlighted_parts: Vec::new(),
                 sentence: self.sentences.get(&sentence_id).unwrap(),
+                score: 0.0,
             })
             .filter_map(|mut r| {
                 if query.filter_map(&mut r) {
@@ -50,4 +131,477 @@ where
                 }
             })
     }
+
+    // one page of results, in corpus order, along with the total number of hits.
+    // if every candidate id is a real match, this skips straight to the page and the total is exact.
+    // otherwise, candidates before the page get checked, and the total counts unchecked candidates.
+    pub fn query_page<'a>(
+        &'a self,
+        query: &'a impl Query<D, S>,
+        offset: usize,
+        limit: usize,
+    ) -> Page<'a, S> {
+        let mut ids: Vec<SentenceId> = query
+            .find_sentence_ids(self, CallerType::TopLevel)
+            .into_iter()
+            .collect();
+        ids.dedup();
+
+        let exact_total = query.exact_ids();
+        let to_result = |id: SentenceId| SearchResult {
+            id,
+            highlighted_parts: Vec::new(),
+            sentence: self.sentences.get(&id).unwrap(),
+            score: 0.0,
+        };
+
+        let results = if exact_total {
+            ids.iter()
+                .skip(offset)
+                .take(limit)
+                .map(|id| to_result(*id))
+                .filter_map(|mut r| query.filter_map(&mut r).then_some(r))
+                .collect()
+        } else {
+            ids.iter()
+                .map(|id| to_result(*id))
+                .filter_map(|mut r| query.filter_map(&mut r).then_some(r))
+                .skip(offset)
+                .take(limit)
+                .collect()
+        };
+
+        Page {
+            results,
+            total_hits: ids.len(),
+            exact_total,
+        }
+    }
+
+    // like query_page, but grouping hits by document and paginating over documents
+    pub fn query_documents<'a, Doc: Archive>(
+        &'a self,
+        query: &'a impl Query<D, S>,
+        documents: &'a RkyvMap<u32, Doc>,
+        offset: usize,
+        limit: usize,
+    ) -> DocumentPage<'a, Doc, S> {
+        let mut ids: Vec<SentenceId> = query
+            .find_sentence_ids(self, CallerType::TopLevel)
+            .into_iter()
+            .collect();
+        ids.dedup();
+
+        let groups: Vec<&[SentenceId]> = ids.chunk_by(|a, b| a.doc == b.doc).collect();
+        let exact_total = query.exact_ids();
+
+        let to_hits = |group: &[SentenceId]| {
+            let sentences: Vec<SearchResult<'a, S>> = group
+                .iter()
+                .map(|id| SearchResult {
+                    id: *id,
+                    highlighted_parts: Vec::new(),
+                    sentence: self.sentences.get(id).unwrap(),
+                    score: 0.0,
+                })
+                .filter_map(|mut r| query.filter_map(&mut r).then_some(r))
+                .collect();
+
+            DocumentHits {
+                id: group[0].doc,
+                document: documents.get(&group[0].doc).unwrap(),
+                sentences,
+            }
+        };
+
+        let documents = if exact_total {
+            groups
+                .iter()
+                .skip(offset)
+                .take(limit)
+                .map(|group| to_hits(group))
+                .collect()
+        } else {
+            groups
+                .iter()
+                .map(|group| to_hits(group))
+                .filter(|hits| !hits.sentences.is_empty())
+                .skip(offset)
+                .take(limit)
+                .collect()
+        };
+
+        DocumentPage {
+            documents,
+            total_documents: groups.len(),
+            exact_total,
+        }
+    }
+
+    // the hits along with `before` and `after` sentences around each of them. hits whose
+    // context windows overlap or touch end up in the same snippet
+    pub fn with_context<'a>(
+        &'a self,
+        results: impl IntoIterator<Item = SearchResult<'a, S>>,
+        before: u32,
+        after: u32,
+    ) -> Vec<Snippet<'a, S>> {
+        let mut results: Vec<SearchResult<'a, S>> = results.into_iter().collect();
+        results.sort_by_key(|r| r.id);
+        results.dedup_by_key(|r| r.id);
+
+        let mut snippets = Vec::new();
+        // the snippet being built, and the last sentence its context reaches
+        let mut current: Option<(Snippet<'a, S>, u32)> = None;
+
+        for hit in results {
+            let SentenceId { doc, sentence } = hit.id;
+            let start = sentence.saturating_sub(before);
+
+            let merge = matches!(
+                &current,
+                Some((snippet, end)) if snippet.doc == doc && start <= end.saturating_add(1)
+            );
+
+            if !merge {
+                if let Some((snippet, end)) = current.take() {
+                    snippets.push(self.finish_snippet(snippet, end));
+                }
+
+                let mut snippet = Snippet {
+                    doc,
+                    sentences: Vec::new(),
+                    hits: Vec::new(),
+                };
+                self.push_context(&mut snippet, start..sentence);
+                current = Some((snippet, sentence));
+            }
+
+            let (snippet, end) = current.as_mut().unwrap();
+
+            if merge {
+                let last = snippet.sentences.last().unwrap().id.sentence;
+                self.push_context(snippet, last + 1..sentence);
+            }
+
+            snippet.hits.push(snippet.sentences.len());
+            snippet.sentences.push(hit);
+            *end = std::cmp::max(*end, sentence.saturating_add(after));
+        }
+
+        if let Some((snippet, end)) = current {
+            snippets.push(self.finish_snippet(snippet, end));
+        }
+
+        snippets
+    }
+
+    // appends the document's sentences in `range` as context, stopping at the end of the document
+    fn push_context<'a>(&'a self, snippet: &mut Snippet<'a, S>, range: Range<u32>) {
+        for sentence in range {
+            let id = SentenceId::new(snippet.doc, sentence);
+            let Some(sentence) = self.sentences.get(&id) else {
+                break;
+            };
+
+            snippet.sentences.push(SearchResult {
+                id,
+                highlighted_parts: Vec::new(),
+                sentence,
+                score: 0.0,
+            });
+        }
+    }
+
+    fn finish_snippet<'a>(&'a self, mut snippet: Snippet<'a, S>, end: u32) -> Snippet<'a, S> {
+        let last = snippet.sentences.last().unwrap().id.sentence;
+        self.push_context(&mut snippet, last + 1..end.saturating_add(1));
+
+        snippet
+    }
+
+    pub fn stats(&self) -> &CorpusStats {
+        &self.stats
+    }
+
+    // the query's terms, each paired with its idf over `total` units,
+    // given the number of units that contain it
+    fn term_idfs(
+        &self,
+        query: &impl Query<D, S>,
+        bm25: &Bm25,
+        total: u64,
+        frequency: impl Fn(Postings) -> u64,
+    ) -> Vec<(u32, f32)> {
+        let mut terms = Vec::new();
+        query.collect_terms(&mut terms);
+        terms.sort_unstable();
+        terms.dedup();
+
+        terms
+            .into_iter()
+            .filter_map(|term| {
+                let postings = self.index.get(&term)?;
+                Some((term, bm25.idf(frequency(postings), total)))
+            })
+            .collect()
+    }
+
+    // the k best matching sentences by bm25, highest score first
+    pub fn query_ranked<'a>(
+        &'a self,
+        query: &'a impl Query<D, S>,
+        k: usize,
+        bm25: &Bm25,
+    ) -> Vec<SearchResult<'a, S>> {
+        let terms = self.term_idfs(query, bm25, self.stats.sentences, |postings| {
+            postings.len() as u64
+        });
+        let mut top = TopK::new(k);
+
+        let mut ids: Vec<SentenceId> = query
+            .find_sentence_ids(self, CallerType::TopLevel)
+            .into_iter()
+            .collect();
+        ids.dedup();
+
+        for id in ids {
+            let sentence = self.sentences.get(&id).unwrap();
+            let score = bm25.score_sentence(&terms, sentence, &self.stats);
+
+            // scoring is cheaper than highlighting, so only contenders get checked
+            if !top.accepts(score) {
+                continue;
+            }
+
+            let mut result = SearchResult {
+                id,
+                highlighted_parts: Vec::new(),
+                sentence,
+                score,
+            };
+
+            if query.filter_map(&mut result) {
+                top.push(score, result);
+            }
+        }
+
+        top.into_sorted_vec().into_iter().map(|v| v.value).collect()
+    }
+
+    // the k best matching documents by bm25 over their whole text, highest score first
+    pub fn rank_documents(
+        &self,
+        query: &impl Query<D, S>,
+        k: usize,
+        bm25: &Bm25,
+    ) -> Vec<RankedDocument> {
+        let terms = self.term_idfs(query, bm25, self.stats.documents, document_frequency);
+        let avg_len = self.stats.avg_doc_len();
+
+        let mut top = TopK::new(k);
+        let mut current: Option<(u32, usize)> = None;
+
+        let mut score_document = |doc: u32, hits: usize| {
+            let len = self.stats.doc_len(doc);
+            let score: f32 = terms
+                .iter()
+                .map(|(term, idf)| {
+                    let postings = self
+                        .index
+                        .get(term)
+                        .map(|postings| postings.document(doc))
+                        .unwrap_or_default();
+                    let tf: usize = postings
+                        .iter()
+                        .filter_map(|id| self.sentences.get(id)?.terms_by_value.get(term))
+                        .map(|positions| positions.len())
+                        .sum();
+
+                    if tf == 0 {
+                        0.0
+                    } else {
+                        bm25.term_score(*idf, tf as u32, len, avg_len)
+                    }
+                })
+                .sum();
+
+            top.push(
+                score,
+                RankedDocument {
+                    id: doc,
+                    score,
+                    hits,
+                },
+            );
+        };
+
+        for result in self.query(query) {
+            match current {
+                Some((doc, ref mut hits)) if doc == result.id.doc => *hits += 1,
+                _ => {
+                    if let Some((doc, hits)) = current {
+                        score_document(doc, hits);
+                    }
+
+                    current = Some((result.id.doc, 1));
+                }
+            }
+        }
+
+        if let Some((doc, hits)) = current {
+            score_document(doc, hits);
+        }
+
+        top.into_sorted_vec().into_iter().map(|v| v.value).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ranking::Bm25, test::database};
+
+    #[test]
+    fn test_with_context_at_document_edges() {
+        let docs = [
+            "Dragon one.\nTwo.\nThree.\nFour.\nDragon five.",
+            "A lone dragon.",
+        ];
+        let db = database("searcher_context", &docs, |_| {});
+        let query = db.parse_query("dragon", (), true).unwrap();
+
+        // (doc, sentences, hits) of each snippet
+        let windows = |before, after| -> Vec<(u32, Vec<u32>, Vec<usize>)> {
+            db.with_context(db.query(&query), before, after)
+                .into_iter()
+                .map(|snippet| {
+                    let sentences = snippet.sentences.iter().map(|s| s.id.sentence).collect();
+                    (snippet.doc, sentences, snippet.hits)
+                })
+                .collect()
+        };
+
+        // windows are cut short at the first and last sentence of a document, and at both ends
+        // of a document shorter than the window
+        assert_eq!(
+            windows(1, 1),
+            [
+                (1, vec![0, 1], vec![0]),
+                (1, vec![3, 4], vec![1]),
+                (2, vec![0], vec![0]),
+            ]
+        );
+
+        // overlapping windows merge, and never reach past the document
+        assert_eq!(
+            windows(5, 5),
+            [(1, vec![0, 1, 2, 3, 4], vec![0, 4]), (2, vec![0], vec![0])]
+        );
+    }
+
+    #[test]
+    fn test_query_ranked() {
+        let docs = [
+            "The dragon sleeps in a long and winding cave under the old mountain.",
+            "Dragon, dragon!",
+            "A dragon.",
+        ];
+        let db = database("searcher_ranked", &docs, |_| {});
+        let ranked = |query: &str, k: usize| -> Vec<u32> {
+            let query = db.parse_query(query, (), true).unwrap();
+            db.query_ranked(&query, k, &Bm25::default())
+                .into_iter()
+                .map(|result| result.id.doc)
+                .collect()
+        };
+
+        // more occurrences first, then shorter sentences
+        assert_eq!(ranked("dragon", 10), [2, 3, 1]);
+        assert_eq!(ranked("dragon", 2), [2, 3]);
+        assert_eq!(ranked("dragon", usize::MAX), [2, 3, 1]);
+
+        // sentences matched by several clauses still come up once
+        assert_eq!(ranked("dragon OR dragon", 10), [2, 3, 1]);
+    }
+
+    #[test]
+    fn test_rank_documents() {
+        let docs = [
+            "Dragon here.\nNothing else.\nNothing else.\nNothing else.",
+            "Dragon here.\nDragon there.",
+            "A dragon sleeps.",
+        ];
+        let db = database("searcher_rank_documents", &docs, |_| {});
+        let query = db.parse_query("dragon", (), true).unwrap();
+
+        // (doc, hits), best first
+        let ranked: Vec<(u32, usize)> = db
+            .rank_documents(&query, 10, &Bm25::default())
+            .into_iter()
+            .map(|doc| (doc.id, doc.hits))
+            .collect();
+        assert_eq!(ranked, [(2, 2), (3, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn test_query_page() {
+        let docs = [
+            "Dragon one.\nDragon two.\nNo match.",
+            "A dragon.",
+            "Red dragon sleeps.\nThe red scales of a dragon.",
+            "Dragon, red and angry.",
+        ];
+        let db = database("searcher_query_page", &docs, |_| {});
+
+        // (ids on the page, total hits, whether the total is exact)
+        let page = |query: &str, offset, limit| {
+            let query = db.parse_query(query, (), true).unwrap();
+            let page = db.query_page(&query, offset, limit);
+            let ids: Vec<(u32, u32)> = page
+                .results
+                .iter()
+                .map(|result| (result.id.doc, result.id.sentence))
+                .collect();
+            (ids, page.total_hits, page.exact_total)
+        };
+
+        // every sentence with the term is a hit, so the total is exact
+        assert_eq!(page("dragon", 1, 2), (vec![(1, 1), (2, 0)], 6, true));
+        assert_eq!(page("dragon", 5, 2), (vec![(4, 0)], 6, true));
+
+        // phrases have to be checked sentence by sentence, so the total is an upper bound that
+        // counts every sentence with both terms
+        assert_eq!(page("\"red dragon\"", 0, 10), (vec![(3, 0)], 3, false));
+        assert_eq!(page("\"red dragon\"", 1, 10), (vec![], 3, false));
+    }
+
+    #[test]
+    fn test_query_documents() {
+        let docs = [
+            "Dragon one.\nDragon two.\nNo match.",
+            "A dragon.",
+            "Red dragon sleeps.\nThe red scales of a dragon.",
+            "Dragon, red and angry.",
+        ];
+        let db = database("searcher_query_documents", &docs, |_| {});
+
+        // (doc, its text, hits) on the page, total documents, and whether that total is exact
+        let page = |query: &str, offset, limit| {
+            let query = db.parse_query(query, (), true).unwrap();
+            let page = db.query_documents(&query, offset, limit);
+            let documents: Vec<(u32, String, usize)> = page
+                .documents
+                .iter()
+                .map(|hits| (hits.id, hits.document.to_string(), hits.hits()))
+                .collect();
+            (documents, page.total_documents, page.exact_total)
+        };
+        let doc = |id: u32, hits| (id, docs[id as usize - 1].to_string(), hits);
+
+        assert_eq!(page("dragon", 0, 2), (vec![doc(1, 2), doc(2, 1)], 4, true));
+        assert_eq!(page("dragon", 2, 2), (vec![doc(3, 2), doc(4, 1)], 4, true));
+
+        // documents whose candidates all turn out not to match are skipped
+        assert_eq!(page("\"red dragon\"", 0, 10), (vec![doc(3, 1)], 2, false));
+    }
 }
@@ -0,0 +1,126 @@
+use std::ops::Range;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::analyzer::Analyzer;
+
+pub type SegmentFn = Box<dyn Fn(&str) -> Vec<Range<usize>> + Send + Sync>;
+
+// how a document gets split into sentences. each segment becomes one Sentence, numbered in order.
+#[derive(Default)]
+pub enum Segmentation {
+    // one sentence per line
+    #[default]
+    Lines,
+    // unicode sentence boundaries (UAX #29)
+    Sentences,
+    // windows of `size` tokens, each sharing `overlap` tokens with the previous one. windows
+    // always advance by at least one token, however large the overlap
+    TokenWindows {
+        size: usize,
+        overlap: usize,
+    },
+    // byte ranges into the document, in order. they're clamped to the document and moved back to
+    // the nearest char boundary
+    Custom(SegmentFn),
+}
+
+impl Segmentation {
+    pub fn custom(f: impl Fn(&str) -> Vec<Range<usize>> + Send + Sync + 'static) -> Segmentation {
+        Segmentation::Custom(Box::new(f))
+    }
+
+    pub fn segment(&self, doc: &str, analyzer: &impl Analyzer) -> Vec<Range<usize>> {
+        match self {
+            Segmentation::Lines => doc.lines().map(|line| subslice_range(doc, line)).collect(),
+            Segmentation::Sentences => doc
+                .unicode_sentences()
+                .map(|sentence| subslice_range(doc, sentence.trim_end()))
+                .collect(),
+            Segmentation::TokenWindows { size, overlap } => {
+                let words = analyzer.words(doc);
+                let size = (*size).max(1);
+                let step = size.saturating_sub(*overlap).max(1);
+
+                let mut windows = Vec::with_capacity(words.len() / step + 1);
+                let mut start = 0;
+
+                while start < words.len() {
+                    let end = std::cmp::min(start + size, words.len());
+                    let (last_start, last_word) = words[end - 1];
+
+                    windows.push(words[start].0..last_start + last_word.len());
+
+                    if end == words.len() {
+                        break;
+                    }
+
+                    start += step;
+                }
+
+                windows
+            }
+            Segmentation::Custom(f) => f(doc)
+                .into_iter()
+                .map(|range| {
+                    let start = char_boundary(doc, range.start);
+                    start..char_boundary(doc, range.end).max(start)
+                })
+                .collect(),
+        }
+    }
+}
+
+#[inline(always)]
+fn subslice_range(doc: &str, part: &str) -> Range<usize> {
+    let start = part.as_ptr() as usize - doc.as_ptr() as usize;
+    start..start + part.len()
+}
+
+// the closest char boundary at or before `idx`
+#[inline(always)]
+fn char_boundary(doc: &str, idx: usize) -> usize {
+    let mut idx = idx.min(doc.len());
+    while !doc.is_char_boundary(idx) {
+        idx -= 1;
+    }
+    idx
+}
+
+#[cfg(test)]
+mod test {
+    use std::ops::Range;
+
+    use super::Segmentation;
+    use crate::analyzer::DefaultAnalyzer;
+
+    fn segments<'a>(segmentation: &Segmentation, doc: &'a str) -> Vec<&'a str> {
+        segmentation
+            .segment(doc, &DefaultAnalyzer::default())
+            .into_iter()
+            .map(|range| &doc[range])
+            .collect()
+    }
+
+    #[test]
+    fn test_token_windows_always_advance() {
+        let doc = "one two three";
+        let windows = |size, overlap| segments(&Segmentation::TokenWindows { size, overlap }, doc);
+
+        assert_eq!(windows(2, 1), ["one two", "two three"]);
+        assert_eq!(windows(2, 2), ["one two", "two three"]);
+        assert_eq!(windows(2, 5), ["one two", "two three"]);
+        assert_eq!(windows(0, 0), ["one", "two", "three"]);
+    }
+
+    #[test]
+    fn test_custom_ranges_clamped() {
+        // "é" takes bytes 1..3
+        let doc = "héllo wörld";
+        let backwards = Range { start: 9, end: 4 };
+        let segmentation =
+            Segmentation::custom(move |_| vec![0..2, 2..6, 7..100, backwards.clone()]);
+
+        assert_eq!(segments(&segmentation, doc), ["h", "éllo", "wörld", ""]);
+    }
+}
@@ -4,9 +4,18 @@ use bytemuck::{Pod, Zeroable};
 
 use rkyv::Archive;
 use smallvec::SmallVec;
+use storage::Compressible;
 
-use crate::{CopyableRange, Token};
-pub type SentenceRange = CopyableRange;
+use crate::Token;
+
+// a highlighted byte range of a sentence's text, along with the class of the query clause that
+// produced it
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SentenceRange {
+    pub start: usize,
+    pub end: usize,
+    pub class: u32,
+}
 
 pub struct SentenceWithHighlights<'a, M: Archive> {
     pub id: SentenceId,
@@ -33,17 +42,53 @@ impl SentenceId {
     }
 }
 
+impl Compressible for SentenceId {
+    #[inline(always)]
+    fn to_u64(self) -> u64 {
+        ((self.doc as u64) << 32) | self.sentence as u64
+    }
+
+    #[inline(always)]
+    fn from_u64(value: u64) -> Self {
+        SentenceId::new((value >> 32) as u32, value as u32)
+    }
+}
+
 #[derive(Clone, Archive, rkyv::Serialize)]
 pub struct Sentence<M> {
     pub text: String,
     pub tokens: Vec<Token>,
-    // (u32 term -> idx in tokens array)
+    // (u32 term -> idx in tokens array), for both stemmed and exact terms
     pub terms_by_value: BTreeMap<u32, SmallVec<[usize; 4]>>,
     pub terms: Vec<u32>,
+    // unstemmed term for each token. the same as the stemmed one, if they share their postings
+    pub exact_terms: Vec<u32>,
     pub metadata: M,
 }
 
+impl<M> Sentence<M> {
+    // rebuilds terms_by_value from terms and exact_terms
+    pub(crate) fn index_terms(&mut self) {
+        self.terms_by_value.clear();
+
+        for (idx, term) in self.terms.iter().chain(&self.exact_terms).enumerate() {
+            self.terms_by_value
+                .entry(*term)
+                .or_default()
+                .push(idx % self.terms.len());
+        }
+
+        for positions in self.terms_by_value.values_mut() {
+            positions.sort_unstable();
+            positions.dedup();
+        }
+    }
+}
+
 pub enum SentencePart<'a> {
     Normal(&'a str),
-    Highlight(&'a str),
+    // the text, and the class of the clause that highlighted it
+    Highlight(&'a str, u32),
+    // marks where a snippet cut text out
+    Ellipsis,
 }
@@ -0,0 +1,194 @@
+use std::ops::Range;
+
+use logos::Logos;
+
+use crate::{
+    analyzer::Analyzer, dictionary::word_pattern, query::parser::QueryToken,
+    ranking::document_frequency, searcher::SearchEngine, term_map::FrozenTermMap, DocumentMetadata,
+    SentenceMetadata,
+};
+
+// a query word the index has never seen
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownWord {
+    pub word: String,
+    // byte range of the word in the query
+    pub span: Range<usize>,
+    // closest known terms, best first
+    pub suggestions: Vec<Suggestion>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Suggestion {
+    // unstemmed, as it appears in the index
+    pub term: String,
+    pub distance: usize,
+    pub document_frequency: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SpellCheck {
+    pub unknown: Vec<UnknownWord>,
+    // the query with each unknown word replaced by its best suggestion, if any had one
+    pub corrected: Option<String>,
+}
+
+// how far off a suggestion may be, by word length. short words have too many close neighbours
+fn max_distance(word: &str) -> usize {
+    match word.chars().count() {
+        0..=4 => 1,
+        _ => 2,
+    }
+}
+
+pub(crate) fn check<D, S>(
+    terms: &FrozenTermMap<impl Analyzer>,
+    db: &SearchEngine<D, S>,
+    query: &str,
+    limit: usize,
+) -> SpellCheck
+where
+    D: DocumentMetadata,
+    S: SentenceMetadata,
+{
+    let tokens: Vec<(QueryToken, Range<usize>)> = QueryToken::lexer(query)
+        .spanned()
+        .filter_map(|(token, span)| Some((token.ok()?, span)))
+        .collect();
+
+    let mut unknown = Vec::new();
+
+    for (idx, (token, span)) in tokens.iter().enumerate() {
+        // the text to check, and where it starts in the query
+        let (text, offset) = match token {
+            // fuzzy terms and patterns are expected not to be known words
+            QueryToken::Ident(_)
+                if matches!(tokens.get(idx + 1), Some((QueryToken::Slop(_), _))) =>
+            {
+                continue
+            }
+            QueryToken::Ident(v) if word_pattern(v).is_some() => continue,
+            QueryToken::Ident(v) => (*v, span.start),
+            // the quotes aren't part of the string
+            QueryToken::QuotedString(v) => (*v, span.start + 1),
+            _ => continue,
+        };
+
+        for (start, word) in terms.analyzer().words(text) {
+            if terms.term(word).is_some() {
+                continue;
+            }
+
+            let normalized = terms.analyzer().normalize(word);
+            let mut suggestions: Vec<Suggestion> = terms
+                .dictionary()
+                .fuzzy(&normalized, max_distance(&normalized))
+                .into_iter()
+                .filter_map(|(term, id, distance)| {
+                    // known, but never indexed, e.g. a stopword
+                    let postings = db.index.get(&id)?;
+
+                    Some(Suggestion {
+                        term: term.to_owned(),
+                        distance,
+                        document_frequency: document_frequency(postings),
+                    })
+                })
+                .collect();
+
+            suggestions.sort_by(|a, b| {
+                a.distance
+                    .cmp(&b.distance)
+                    .then(b.document_frequency.cmp(&a.document_frequency))
+            });
+            suggestions.truncate(limit);
+
+            let start = offset + start;
+            unknown.push(UnknownWord {
+                word: word.to_owned(),
+                span: start..start + word.len(),
+                suggestions,
+            });
+        }
+    }
+
+    let corrected = unknown
+        .iter()
+        .any(|word| !word.suggestions.is_empty())
+        .then(|| {
+            let mut corrected = String::with_capacity(query.len());
+            let mut cursor = 0;
+
+            for word in &unknown {
+                let Some(best) = word.suggestions.first() else {
+                    continue;
+                };
+
+                corrected.push_str(&query[cursor..word.span.start]);
+                corrected.push_str(&best.term);
+                cursor = word.span.end;
+            }
+
+            corrected.push_str(&query[cursor..]);
+            corrected
+        });
+
+    SpellCheck { unknown, corrected }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::database;
+
+    use super::Suggestion;
+
+    #[test]
+    fn test_spell_check() {
+        let docs = [
+            "The dragon sleeps.",
+            "A dragon flies.",
+            "They drag it away.",
+            "A wizard.",
+        ];
+        let db = database("spelling", &docs, |_| {});
+
+        let suggestion = |term: &str, distance, document_frequency| Suggestion {
+            term: term.to_string(),
+            distance,
+            document_frequency,
+        };
+
+        // fuzzy terms and patterns aren't checked, and quoted words are found inside the quotes
+        let query = "drago wizard~1 wiz* \"the dargon\"";
+        let check = db.spell_check(query, 5);
+        let unknown: Vec<(&str, &str, Vec<Suggestion>)> = check
+            .unknown
+            .iter()
+            .map(|word| {
+                let span = &query[word.span.clone()];
+                (word.word.as_str(), span, word.suggestions.clone())
+            })
+            .collect();
+
+        // closest first, then the most common
+        assert_eq!(
+            unknown,
+            [
+                (
+                    "drago",
+                    "drago",
+                    vec![suggestion("dragon", 1, 2), suggestion("drag", 1, 1)]
+                ),
+                ("dargon", "dargon", vec![suggestion("dragon", 2, 2)]),
+            ]
+        );
+        assert_eq!(
+            check.corrected.as_deref(),
+            Some("dragon wizard~1 wiz* \"the dragon\"")
+        );
+
+        let check = db.spell_check("wizard", 5);
+        assert!(check.unknown.is_empty());
+        assert_eq!(check.corrected, None);
+    }
+}
@@ -1,27 +1,49 @@
 use std::collections::{BTreeMap, HashMap};
 
 use perfect_map::PerfectMap;
-use rust_stemmers::Stemmer;
-use smallvec::SmallVec;
 use smartstring::alias::CompactString;
-use unicode_segmentation::UnicodeSegmentation;
 
+use crate::analyzer::{Analyzer, DefaultAnalyzer};
+use crate::dictionary::{Expansion, TermDictionary};
+use crate::segment::Segmentation;
 use crate::sentence::Sentence;
 use crate::Token;
 
 #[derive(Default, Clone, Debug)]
-pub struct TermMap {
+pub struct TermMap<A = DefaultAnalyzer> {
     pub kv: HashMap<CompactString, u32>,
+    // unstemmed forms. these share an id space with kv, so both can live in the same index
+    pub exact: HashMap<CompactString, u32>,
+    pub analyzer: A,
 }
 
-impl TermMap {
+impl<A: Analyzer> TermMap<A> {
+    pub fn new(analyzer: A) -> TermMap<A> {
+        TermMap {
+            kv: HashMap::new(),
+            exact: HashMap::new(),
+            analyzer,
+        }
+    }
+
     pub fn tokenize_all<M>(
         &mut self,
         doc: &str,
         make_metadata: impl Fn(&str) -> M + Clone,
     ) -> Vec<Sentence<M>> {
-        doc.lines()
-            .map(|s| self.tokenize_sentence(s, make_metadata.clone()))
+        self.tokenize_segments(doc, &Segmentation::Lines, make_metadata)
+    }
+
+    pub fn tokenize_segments<M>(
+        &mut self,
+        doc: &str,
+        segmentation: &Segmentation,
+        make_metadata: impl Fn(&str) -> M + Clone,
+    ) -> Vec<Sentence<M>> {
+        segmentation
+            .segment(doc, &self.analyzer)
+            .into_iter()
+            .map(|range| self.tokenize_sentence(&doc[range], make_metadata.clone()))
             .collect()
     }
 
@@ -30,16 +52,17 @@ impl TermMap {
         s: &str,
         make_metadata: impl Fn(&str) -> M,
     ) -> Sentence<M> {
-        let words: Vec<_> = s.unicode_word_indices().collect();
+        let words = self.analyzer.words(s);
 
-        let (mut tokens, mut terms) = (
+        let (mut tokens, mut terms, mut exact_terms) = (
+            Vec::with_capacity(words.len()),
             Vec::with_capacity(words.len()),
             Vec::with_capacity(words.len()),
         );
 
         for (start, word) in words {
-            let lower = word.to_lowercase();
-            let term = self.intern(&lower);
+            let term = self.intern(word);
+            let exact_term = self.intern_exact(word);
 
             tokens.push(Token {
                 start,
@@ -48,53 +71,113 @@ impl TermMap {
             });
 
             terms.push(term);
+            exact_terms.push(exact_term);
         }
 
-        let mut terms_by_value: BTreeMap<u32, SmallVec<[usize; 4]>> = BTreeMap::new();
-        for (idx, term) in terms.iter().enumerate() {
-            terms_by_value.entry(*term).or_default().push(idx);
-        }
-
-        terms_by_value.values_mut().for_each(|v| v.sort());
-
-        Sentence {
+        let mut sentence = Sentence {
             tokens,
             terms,
-            terms_by_value,
+            exact_terms,
+            terms_by_value: BTreeMap::new(),
             text: s.to_owned(),
             metadata: make_metadata(s),
-        }
+        };
+        sentence.index_terms();
+
+        sentence
+    }
+
+    pub fn intern(&mut self, word: &str) -> u32 {
+        let l = self.kv.len() + self.exact.len() + 1;
+        let term = self.analyzer.term(word);
+        *self.kv.entry(term.as_ref().into()).or_insert(l as u32)
     }
 
-    pub fn intern(&mut self, term: &str) -> u32 {
-        let l = self.kv.len() + 1;
-        let term = Stemmer::create(rust_stemmers::Algorithm::English).stem(term);
-        *self.kv.entry(term.into()).or_insert(l as u32)
+    pub fn intern_exact(&mut self, word: &str) -> u32 {
+        let l = self.kv.len() + self.exact.len() + 1;
+        let term = self.analyzer.normalize(word);
+        *self.exact.entry(term.as_ref().into()).or_insert(l as u32)
     }
 
-    pub fn freeze(self) -> FrozenTermMap {
+    pub fn freeze(self) -> FrozenTermMap<A> {
         FrozenTermMap {
+            dictionary: TermDictionary::from_map(&self.exact),
             map: PerfectMap::from_map(self.kv),
+            exact: Some(PerfectMap::from_map(self.exact)),
+            analyzer: self.analyzer,
         }
     }
 }
 
-#[cfg_attr(feature = "persistence", derive(serde::Serialize, serde::Deserialize))]
-pub struct FrozenTermMap {
-    map: PerfectMap<CompactString, u32>,
+pub struct FrozenTermMap<A = DefaultAnalyzer> {
+    pub(crate) map: PerfectMap<CompactString, u32>,
+    // None for indexes from before exact terms existed
+    pub(crate) exact: Option<PerfectMap<CompactString, u32>>,
+    // the keys of `exact`, sorted
+    pub(crate) dictionary: TermDictionary,
+    pub(crate) analyzer: A,
 }
 
-impl FrozenTermMap {
+impl<A: Analyzer> FrozenTermMap<A> {
+    pub fn analyzer(&self) -> &A {
+        &self.analyzer
+    }
+
+    pub fn dictionary(&self) -> &TermDictionary {
+        &self.dictionary
+    }
+
+    // unstemmed terms starting with `prefix`, up to `limit` of them
+    pub fn expand_prefix(&self, prefix: &str, limit: usize) -> Expansion {
+        let prefix = self.analyzer.normalize(prefix);
+        Expansion::collect(self.dictionary.prefix(&prefix), limit)
+    }
+
+    // unstemmed terms matching a `*`/`?` pattern, up to `limit` of them
+    pub fn expand_wildcard(&self, pattern: &str, limit: usize) -> Expansion {
+        let pattern = self.analyzer.normalize(pattern);
+        Expansion::collect(self.dictionary.wildcard(&pattern), limit)
+    }
+
+    // unstemmed terms within `max_distance` edits of `term`, up to `limit` of the closest ones
+    pub fn expand_fuzzy(&self, term: &str, max_distance: usize, limit: usize) -> Expansion {
+        let term = self.analyzer.normalize(term);
+        let matches = self.dictionary.fuzzy(&term, max_distance);
+
+        Expansion::collect(matches.into_iter().map(|(text, id, _)| (text, id)), limit)
+    }
+
     pub fn term(&self, term: &str) -> Option<u32> {
-        let term = term.to_lowercase();
-        let term = Stemmer::create(rust_stemmers::Algorithm::English).stem(&term);
+        let term = self.analyzer.term(term);
         self.map.get(term.as_ref()).copied()
     }
 
+    // falls back to the stemmed term when the index has no exact terms
+    pub fn exact_term(&self, term: &str) -> Option<u32> {
+        match &self.exact {
+            Some(exact) => exact.get(self.analyzer.normalize(term).as_ref()).copied(),
+            None => self.term(term),
+        }
+    }
+
+    pub fn has_exact_terms(&self) -> bool {
+        self.exact.is_some()
+    }
+
     pub fn tokenize_phrase(&self, query: &str) -> Vec<u32> {
-        query
-            .unicode_words()
-            .map(|word| self.term(word).unwrap_or(0u32))
+        self.analyzer
+            .words(query)
+            .into_iter()
+            .map(|(_, word)| self.term(word).unwrap_or(0u32))
+            .collect()
+    }
+
+    // tokenizes a phrase into unstemmed terms, for use with QueryBuilder::exact
+    pub fn tokenize_exact_phrase(&self, query: &str) -> Vec<u32> {
+        self.analyzer
+            .words(query)
+            .into_iter()
+            .map(|(_, word)| self.exact_term(word).unwrap_or(0u32))
             .collect()
     }
 }
@@ -5,11 +5,13 @@ use std::io::ErrorKind;
 use std::io::{self, BufWriter};
 use std::{fmt::Debug, fs::File};
 
+pub mod postings;
 pub mod store;
 
 #[cfg(feature = "persistence")]
 use serde::de::DeserializeOwned;
 
+pub use postings::*;
 pub use store::*;
 
 use ph::fmph::{GOBuildConf, GOConf, GOFunction};
@@ -19,6 +21,7 @@ use rkyv::ser::serializers::{
 
 pub type MultiMap<K, V> = ImmutableMap<K, MultiStorage<V>>;
 pub type RkyvMap<K, V> = ImmutableMap<K, RkyvStorage<V>>;
+pub type CompressedMap<K, V> = ImmutableMap<K, CompressedStorage<V>>;
 
 #[cfg(feature = "persistence")]
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -96,6 +99,51 @@ impl<K: Hash + Sync + Send + Clone + PartialEq + Debug, V: bytemuck::Pod>
     }
 }
 
+impl<K: Hash + Sync + Send + Clone + PartialEq + Debug, V: Compressible>
+    ImmutableMap<K, CompressedStorage<V>>
+{
+    pub fn compressed_from_map(
+        map: HashMap<K, Vec<V>>,
+        file: File,
+    ) -> io::Result<ImmutableMap<K, CompressedStorage<V>>> {
+        let (keys, vals): (Vec<_>, Vec<_>) = map.into_iter().unzip();
+        ImmutableMap::build_compressed(keys, &vals, file)
+    }
+
+    // every value list has to be sorted
+    pub fn build_compressed(
+        keys: Vec<K>,
+        vals: &[impl AsRef<[V]>],
+        file: File,
+    ) -> io::Result<ImmutableMap<K, CompressedStorage<V>>> {
+        assert!(keys.len() == vals.len());
+
+        let mut conf = GOBuildConf::with_lsize(GOConf::default(), 300);
+        conf.cache_threshold = 0;
+
+        let hasher = GOFunction::from_slice_with_conf(&keys, conf);
+
+        let mut storage_builder = CompressedStorageBuilder::new(vals.len(), file);
+        let mut reordered_keys: Vec<K> = Vec::with_capacity(keys.len());
+
+        for (k, v) in keys.into_iter().zip(vals.iter()) {
+            let new_idx = hasher.get(&k).unwrap() as usize;
+            storage_builder.serialize(new_idx, v.as_ref())?;
+            reordered_keys.spare_capacity_mut()[new_idx].write(k);
+        }
+
+        unsafe {
+            reordered_keys.set_len(vals.len());
+        }
+
+        Ok(ImmutableMap {
+            hasher,
+            keys: reordered_keys,
+            store: storage_builder.finish()?,
+        })
+    }
+}
+
 impl<K: Hash + Sync + Send + Clone + PartialEq + Debug, V: SerializableToFile>
     ImmutableMap<K, RkyvStorage<V>>
 {
@@ -0,0 +1,460 @@
+use std::io::{self, BufWriter, Write};
+use std::{fs::File, marker::PhantomData};
+
+use memmap2::{Mmap, MmapOptions};
+
+#[cfg(feature = "persistence")]
+use crate::PersistentStorage;
+use crate::Storage;
+
+// values per block. each block can be decoded on its own
+pub const BLOCK_LEN: usize = 128;
+
+// a skip entry is the block's first value (u64) followed by its offset into the block data (u32)
+const SKIP_ENTRY: usize = 12;
+
+// values that map to a u64 key preserving their order, so sorted lists of them can be delta encoded
+pub trait Compressible: Copy + Ord {
+    fn to_u64(self) -> u64;
+
+    fn from_u64(value: u64) -> Self;
+}
+
+impl Compressible for u32 {
+    fn to_u64(self) -> u64 {
+        self as u64
+    }
+
+    fn from_u64(value: u64) -> Self {
+        value as u32
+    }
+}
+
+impl Compressible for u64 {
+    fn to_u64(self) -> u64 {
+        self
+    }
+
+    fn from_u64(value: u64) -> Self {
+        value
+    }
+}
+
+fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+
+    out.push(value as u8);
+}
+
+#[inline(always)]
+fn read_varint(data: &[u8], pos: &mut usize) -> u64 {
+    let mut value = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+
+        if byte < 0x80 {
+            return value;
+        }
+
+        shift += 7;
+    }
+}
+
+// sorted lists, stored as blocks of varint deltas with a skip table in front of them
+pub struct CompressedStorage<T> {
+    // byte offset, length in bytes, number of values
+    positions: Vec<(usize, usize, usize)>,
+    store: Mmap,
+    spooky: PhantomData<T>,
+}
+
+pub struct CompressedStorageBuilder<T: Compressible> {
+    cursor: usize,
+    positions: Vec<(usize, usize, usize)>,
+    out: BufWriter<File>,
+    buf: Vec<u8>,
+    spooky: PhantomData<T>,
+}
+
+impl<T: Compressible> CompressedStorageBuilder<T> {
+    pub fn new(length: usize, file: File) -> CompressedStorageBuilder<T> {
+        CompressedStorageBuilder {
+            cursor: 0,
+            positions: vec![(0, 0, 0); length],
+            out: BufWriter::new(file),
+            buf: Vec::new(),
+            spooky: PhantomData,
+        }
+    }
+
+    // `value` has to be sorted
+    pub fn serialize(&mut self, index: usize, value: &[T]) -> io::Result<()> {
+        assert!(value.windows(2).all(|w| w[0] <= w[1]));
+
+        let blocks = value.len().div_ceil(BLOCK_LEN);
+        let mut skips = Vec::with_capacity(blocks * SKIP_ENTRY);
+        self.buf.clear();
+
+        for block in value.chunks(BLOCK_LEN) {
+            let first = block[0].to_u64();
+            skips.extend_from_slice(&first.to_le_bytes());
+            skips.extend_from_slice(&(self.buf.len() as u32).to_le_bytes());
+
+            let mut last = first;
+            for v in &block[1..] {
+                let v = v.to_u64();
+                write_varint(v - last, &mut self.buf);
+                last = v;
+            }
+        }
+
+        self.out.write_all(&skips)?;
+        self.out.write_all(&self.buf)?;
+
+        let len = skips.len() + self.buf.len();
+        self.positions[index] = (self.cursor, len, value.len());
+        self.cursor += len;
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<CompressedStorage<T>> {
+        self.out.flush()?;
+        let (file, _) = self.out.into_parts();
+
+        let map = unsafe { MmapOptions::new().populate().map(&file)? };
+
+        Ok(CompressedStorage {
+            positions: self.positions,
+            store: map,
+            spooky: PhantomData,
+        })
+    }
+}
+
+impl<T: Compressible> CompressedStorage<T> {
+    #[inline(always)]
+    fn list(&self, (pos, bytes, len): (usize, usize, usize)) -> PostingList<'_, T> {
+        let (skips, data) =
+            self.store[pos..pos + bytes].split_at(len.div_ceil(BLOCK_LEN) * SKIP_ENTRY);
+
+        PostingList {
+            len,
+            skips,
+            data,
+            spooky: PhantomData,
+        }
+    }
+}
+
+impl<T: Compressible> Storage for CompressedStorage<T> {
+    type Item<'a>
+        = PostingList<'a, T>
+    where
+        Self: 'a;
+
+    fn try_get(&self, idx: usize) -> Option<Self::Item<'_>> {
+        self.positions.get(idx).map(|position| self.list(*position))
+    }
+
+    unsafe fn get_unchecked(&self, idx: usize) -> Self::Item<'_> {
+        self.list(*self.positions.get_unchecked(idx))
+    }
+}
+
+#[cfg(feature = "persistence")]
+impl<T: Compressible> PersistentStorage for CompressedStorage<T> {
+    type Header = Vec<(usize, usize, usize)>;
+
+    fn header(self) -> Self::Header {
+        self.positions
+    }
+
+    fn load(header: Self::Header, f: File) -> io::Result<Self> {
+        Ok(CompressedStorage {
+            positions: header,
+            store: unsafe { Mmap::map(&f)? },
+            spooky: PhantomData,
+        })
+    }
+}
+
+// one compressed list, borrowed from the storage
+pub struct PostingList<'a, T> {
+    len: usize,
+    skips: &'a [u8],
+    data: &'a [u8],
+    spooky: PhantomData<T>,
+}
+
+impl<T> Clone for PostingList<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for PostingList<'_, T> {}
+
+impl<'a, T: Compressible> PostingList<'a, T> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.skips.len() / SKIP_ENTRY
+    }
+
+    // the first value of a block, and where its deltas start in the block data
+    #[inline(always)]
+    fn skip(&self, block: usize) -> (u64, usize) {
+        let entry = &self.skips[block * SKIP_ENTRY..(block + 1) * SKIP_ENTRY];
+        let first = u64::from_le_bytes(entry[..8].try_into().unwrap());
+        let offset = u32::from_le_bytes(entry[8..].try_into().unwrap());
+
+        (first, offset as usize)
+    }
+
+    #[inline(always)]
+    fn block_len(&self, block: usize) -> usize {
+        BLOCK_LEN.min(self.len - block * BLOCK_LEN)
+    }
+
+    // the last block that could contain `value`, going by the skip table
+    fn find_block(&self, from: usize, value: u64) -> usize {
+        let (mut lo, mut hi) = (from + 1, self.block_count());
+
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if self.skip(mid).0 <= value {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+
+        lo - 1
+    }
+
+    pub fn decode_block(&self, block: usize, out: &mut Vec<T>) {
+        let (first, mut pos) = self.skip(block);
+        let mut value = first;
+        out.push(T::from_u64(value));
+
+        for _ in 1..self.block_len(block) {
+            value += read_varint(self.data, &mut pos);
+            out.push(T::from_u64(value));
+        }
+    }
+
+    pub fn decode(&self) -> Vec<T> {
+        let mut out = Vec::with_capacity(self.len);
+        for block in 0..self.block_count() {
+            self.decode_block(block, &mut out);
+        }
+
+        out
+    }
+
+    // only decodes the one block that could hold `value`
+    pub fn contains(&self, value: T) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        let value = value.to_u64();
+        let block = self.find_block(0, value);
+        let (mut current, mut pos) = self.skip(block);
+
+        for _ in 1..self.block_len(block) {
+            if current >= value {
+                break;
+            }
+
+            current += read_varint(self.data, &mut pos);
+        }
+
+        current == value
+    }
+
+    pub fn iter(&self) -> PostingIter<'a, T> {
+        PostingIter {
+            cursor: self.cursor(),
+            remaining: self.len,
+        }
+    }
+
+    pub fn cursor(&self) -> PostingCursor<'a, T> {
+        PostingCursor {
+            list: *self,
+            block: 0,
+            idx: 0,
+            pos: 0,
+            current: None,
+        }
+    }
+}
+
+// walks a list forwards, using the skip table to jump over blocks that can't hold a target
+pub struct PostingCursor<'a, T> {
+    list: PostingList<'a, T>,
+    block: usize,
+    // index of the current value in its block
+    idx: usize,
+    // byte position of the next delta
+    pos: usize,
+    current: Option<u64>,
+}
+
+impl<'a, T: Compressible> PostingCursor<'a, T> {
+    fn enter_block(&mut self, block: usize) {
+        let (first, pos) = self.list.skip(block);
+        self.block = block;
+        self.idx = 0;
+        self.pos = pos;
+        self.current = Some(first);
+    }
+
+    pub fn current(&self) -> Option<T> {
+        self.current.map(T::from_u64)
+    }
+
+    pub fn advance(&mut self) -> Option<T> {
+        match self.current {
+            None if self.block == 0 && self.idx == 0 && !self.list.is_empty() => {
+                self.enter_block(0)
+            }
+            None => return None,
+            Some(current) if self.idx + 1 < self.list.block_len(self.block) => {
+                self.idx += 1;
+                self.current = Some(current + read_varint(self.list.data, &mut self.pos));
+            }
+            Some(_) if self.block + 1 < self.list.block_count() => self.enter_block(self.block + 1),
+            Some(_) => {
+                // exhausted. moving the index past the block keeps the cursor from restarting
+                self.idx = BLOCK_LEN;
+                self.current = None;
+            }
+        }
+
+        self.current()
+    }
+
+    // moves to the first value that's at least `target`, and returns it
+    pub fn seek(&mut self, target: T) -> Option<T> {
+        let target = target.to_u64();
+
+        if self.current.is_none() {
+            self.advance()?;
+        }
+
+        let current = self.current?;
+        if current >= target {
+            return self.current();
+        }
+
+        let block = self.list.find_block(self.block, target);
+        if block != self.block {
+            self.enter_block(block);
+        }
+
+        while self.current? < target {
+            self.advance()?;
+        }
+
+        self.current()
+    }
+}
+
+pub struct PostingIter<'a, T> {
+    cursor: PostingCursor<'a, T>,
+    remaining: usize,
+}
+
+impl<T: Compressible> Iterator for PostingIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.cursor.advance()?;
+        self.remaining -= 1;
+        Some(next)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T: Compressible> ExactSizeIterator for PostingIter<'_, T> {}
+
+#[cfg(test)]
+mod test {
+    use std::fs::OpenOptions;
+
+    use super::*;
+
+    fn build(lists: &[Vec<u64>], name: &str) -> CompressedStorage<u64> {
+        let path = std::env::temp_dir().join(name);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .unwrap();
+
+        let mut builder = CompressedStorageBuilder::new(lists.len(), file);
+        for (idx, list) in lists.iter().enumerate() {
+            builder.serialize(idx, list).unwrap();
+        }
+
+        builder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let lists = vec![
+            vec![],
+            vec![7],
+            (0..1000).map(|v| v * v).collect(),
+            (0..300).map(|v| ((v / 3) << 32) | (v % 3)).collect(),
+        ];
+        let storage = build(&lists, "joie_postings_roundtrip.test");
+
+        for (idx, list) in lists.iter().enumerate() {
+            let compressed = storage.get(idx);
+            assert_eq!(&compressed.decode(), list);
+            assert_eq!(&compressed.iter().collect::<Vec<_>>(), list);
+            assert_eq!(compressed.iter().len(), list.len());
+        }
+    }
+
+    #[test]
+    fn test_seek() {
+        let list: Vec<u64> = (0..1000).map(|v| v * 3).collect();
+        let storage = build(&[list], "joie_postings_seek.test");
+        let compressed = storage.get(0);
+
+        assert!(compressed.contains(0));
+        assert!(compressed.contains(999));
+        assert!(!compressed.contains(1000));
+        assert!(!compressed.contains(5000));
+
+        let mut cursor = compressed.cursor();
+        assert_eq!(cursor.seek(1), Some(3));
+        assert_eq!(cursor.seek(3), Some(3));
+        assert_eq!(cursor.seek(1000), Some(1002));
+        assert_eq!(cursor.seek(2997), Some(2997));
+        assert_eq!(cursor.seek(2998), None);
+    }
+}